@@ -15,18 +15,13 @@
 //! $ maelstrom test -w echo --bin target/release/examples/echo --time-limit 10
 //! ```
 use std::env;
-use std::sync::atomic::AtomicU64;
-use std::sync::atomic::Ordering::SeqCst;
-use std::sync::Arc;
 
 use async_trait::async_trait;
 use log::{info, warn};
-use tokio::spawn;
 
 use async_maelstrom::msg::Body::Workload;
 use async_maelstrom::msg::Echo;
-use async_maelstrom::msg::{Msg, MsgId};
-use async_maelstrom::process::{ProcNet, Process};
+use async_maelstrom::process::{Event, ProcNet, Process};
 use async_maelstrom::runtime::Runtime;
 use async_maelstrom::{Id, Status};
 
@@ -34,73 +29,49 @@ use async_maelstrom::{Id, Status};
 ///
 /// The server will run until the runtime shuts it down.
 /// It will echo all valid echo requests, and ignore other messages.
+#[derive(Default)]
 struct EchoServer {
     args: Vec<String>,
     net: ProcNet<Echo, ()>,
     id: Id,
     ids: Vec<Id>,
-    msg_id: AtomicU64,
-}
-
-impl Default for EchoServer {
-    fn default() -> Self {
-        Self {
-            args: Default::default(),
-            net: Default::default(),
-            id: Default::default(),
-            ids: Default::default(),
-            msg_id: Default::default(),
-        }
-    }
-}
-
-impl EchoServer {
-    fn next_msg_id(&self) -> MsgId {
-        self.msg_id.fetch_add(1, SeqCst)
-    }
 }
 
 #[async_trait]
 impl Process<Echo, ()> for EchoServer {
-    fn init(
+    async fn init(
         &mut self,
         args: Vec<String>,
         net: ProcNet<Echo, ()>,
         id: Id,
         ids: Vec<Id>,
-        start_msg_id: MsgId,
-    ) {
+    ) -> Status {
         self.args = args;
         self.net = net;
         self.id = id;
         self.ids = ids;
-        self.msg_id = AtomicU64::new(start_msg_id)
+        Ok(())
     }
 
     async fn run(&self) -> Status {
         loop {
             // Respond to all echo messages with an echo_ok message echoing the `echo` field
             match self.net.rxq.recv().await {
-                Ok(Msg {
-                    src,
-                    body: Workload(Echo::Echo { msg_id, echo }),
-                    ..
-                }) => {
-                    self.net
-                        .txq
-                        .send(Msg {
-                            src: self.id.clone(),
-                            dest: src,
-                            body: Workload(Echo::EchoOk {
-                                in_reply_to: msg_id,
-                                msg_id: Some(self.next_msg_id()),
-                                echo,
-                            }),
-                        })
-                        .await?;
-                }
+                Ok(Event::Msg(msg)) => match &msg.body {
+                    Workload(Echo::Echo { echo, .. }) => {
+                        let reply = msg.reply(|in_reply_to| {
+                            Workload(Echo::EchoOk {
+                                in_reply_to,
+                                msg_id: Some(self.net.next_msg_id()),
+                                echo: echo.clone(),
+                            })
+                        })?;
+                        self.net.txq.send(reply).await?;
+                    }
+                    _ => warn!("received and ignoring an unexpected message: {:?}", msg),
+                },
                 Err(_) => return Ok(()), // Runtime is shutting down.
-                Ok(msg) => warn!("received and ignoring an unexpected message: {:?}", msg),
+                Ok(event) => warn!("received and ignoring an unexpected event: {:?}", event),
             };
         }
     }
@@ -109,7 +80,8 @@ impl Process<Echo, ()> for EchoServer {
 /// Run an echo server
 ///
 /// See module level docs for details.
-#[tokio::main]
+#[cfg_attr(feature = "tokio", tokio::main)]
+#[cfg_attr(not(feature = "tokio"), async_std::main)]
 async fn main() -> Status {
     // Log to stderr where Maelstrom will capture it
     env_logger::init();
@@ -117,17 +89,13 @@ async fn main() -> Status {
 
     // Create an echo process and a runtime to execute it
     let process: EchoServer = Default::default();
-    let r = Arc::new(Runtime::new(env::args().collect(), process).await?);
-
-    // Drive the runtime, and ...
-    let (r1, r2, r3) = (r.clone(), r.clone(), r.clone());
-    let t1 = spawn(async move { r1.run_io_egress().await });
-    let t2 = spawn(async move { r2.run_io_ingress().await });
-    let t3 = spawn(async move { r3.run_process().await });
+    let r = Runtime::builder(env::args().collect(), process)
+        .build()
+        .await?;
 
-    // ... wait until the Maelstrom system closes stdin and stdout
+    // Drive the runtime until the Maelstrom system closes stdin and stdout
     info!("running");
-    let _ignored = tokio::join!(t1, t2, t3);
+    r.run().await?;
 
     info!("stopped");
 
@@ -0,0 +1,64 @@
+//! Executor backend for IO, timers and tasks
+//!
+//! The backend is selected by the `async-std` and `tokio` cargo features, with `tokio` taking
+//! precedence when both are enabled. Channels are executor agnostic, and the same under both.
+use std::future::Future;
+use std::time::Duration;
+
+use futures::future::{abortable, AbortHandle};
+
+/// Wait for `duration`
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(not(feature = "tokio"))]
+    async_std::task::sleep(duration).await;
+}
+
+/// Wait for `future` to complete within `duration`
+///
+/// Return [None] IFF `future` did not complete in time
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    #[cfg(feature = "tokio")]
+    let output = tokio::time::timeout(duration, future).await;
+    #[cfg(not(feature = "tokio"))]
+    let output = async_std::future::timeout(duration, future).await;
+    output.ok()
+}
+
+/// Spawn `future` as a task on the executor
+///
+/// Return a future of the task's output. A panic in the task is resumed when the output is awaited.
+/// Dropping the returned future cancels the task, which then stops at its next await point.
+pub(crate) fn spawn<F>(future: F) -> impl Future<Output = F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (future, abort) = abortable(future);
+    #[cfg(feature = "tokio")]
+    let task = {
+        let task = tokio::spawn(future);
+        async move {
+            task.await
+                .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+        }
+    };
+    #[cfg(not(feature = "tokio"))]
+    let task = async_std::task::spawn(future);
+    let cancel = CancelOnDrop(abort);
+    async move {
+        let output = task.await;
+        drop(cancel);
+        output.expect("task only aborted when its output is dropped")
+    }
+}
+
+/// Abort a task when dropped
+struct CancelOnDrop(AbortHandle);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
@@ -11,49 +11,186 @@
 //! - a `Msg` implementation for creating and parsing workload and node-to-node message according to the
 //!   [Maelstrom message protocol](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#messages)
 //! - a `Process` trait for implementing application node processes
+//! - a handler style `Node` trait, as a simpler alternative to implementing a `Process`
 //! - a `Runtime` for driving processes and communicating with the
 //!   [Maelstrom network](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#nodes-and-networks)
+//! - `Transport`s connecting a `Runtime` to Maelstrom over `stdin` and `stdout`, or to in-process
+//!   tests and local routers over channels, Unix domain sockets and TCP
+//!
+//! The `async-std` (default) and `tokio` cargo features select the executor backend for IO,
+//! timers and spawned handler tasks. Enable `tokio`, and disable the default features, to run
+//! everything on a tokio runtime.
 //!
 //! See the [echo.rs](https://github.com/bnjmnt/async-maelstrom/blob/main/examples/echo.rs) for a
 //! simple  library usage example.
 use std::fmt::{Debug, Display, Formatter};
 use std::{error, io};
 
-use async_std::channel::{RecvError, SendError};
+use async_channel::{RecvError, SendError};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::Value;
 
 use crate::msg::Msg;
-use crate::Error::{Deserialize, Shutdown, IO};
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+use crate::process::Event;
+use crate::Error::{Deserialize, Reply, Shutdown, IO};
+
+#[cfg(not(any(feature = "async-std", feature = "tokio")))]
+compile_error!("either the `async-std` or the `tokio` feature must be enabled");
 
+// Modules using the executor backend are left out without one, so only the error above is reported
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+mod backend;
 pub mod msg;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+pub mod node;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
 pub mod process;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+pub mod rpc;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
 pub mod runtime;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+pub mod timer;
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+pub mod transport;
 
 /// Maelstrom [node address](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#messages)
 pub type Id = String;
 
 /// Maelstrom [error code](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#errors)
-pub type ErrorCode = u64;
+///
+/// Codes are serialized as their numeric value. Codes that are not defined by Maelstrom are
+/// represented by [ErrorCode::Custom].
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[serde(from = "u64", into = "u64")]
+pub enum ErrorCode {
+    /// The requested operation could not be completed within a timeout
+    Timeout,
+    /// The requested operation was sent to a node that does not exist
+    NodeNotFound,
+    /// The requested operation is not supported by the node
+    NotSupported,
+    /// The operation definitely cannot be performed at this time
+    TemporarilyUnavailable,
+    /// The client's request did not conform to the server's expectations
+    MalformedRequest,
+    /// The node hit an error it could not recover from
+    Crash,
+    /// The requested operation was aborted
+    Abort,
+    /// The client requested an operation on a key which does not exist
+    KeyDoesNotExist,
+    /// The client tried to create a key which already exists
+    KeyAlreadyExists,
+    /// The requested operation expected some condition to hold, and that assumption was false
+    PreconditionFailed,
+    /// The requested transaction has been aborted because of a conflict with another transaction
+    TxnConflict,
+    /// An application defined error code
+    ///
+    /// Maelstrom recommends application codes of 1000 and above. Only use this variant for codes
+    /// not defined above: those are deserialized into their named variant, so e.g.
+    /// `Custom(11)` does not round trip. Use [ErrorCode::custom] to construct a code that does.
+    Custom(u64),
+}
+
+impl ErrorCode {
+    /// Create an error code from an application defined numeric code
+    ///
+    /// Codes defined by Maelstrom are normalized into their named variant.
+    pub fn custom(code: u64) -> Self {
+        Self::from(code)
+    }
+
+    /// Get the numeric error code
+    pub fn code(&self) -> u64 {
+        match self {
+            ErrorCode::Timeout => 0,
+            ErrorCode::NodeNotFound => 1,
+            ErrorCode::NotSupported => 10,
+            ErrorCode::TemporarilyUnavailable => 11,
+            ErrorCode::MalformedRequest => 12,
+            ErrorCode::Crash => 13,
+            ErrorCode::Abort => 14,
+            ErrorCode::KeyDoesNotExist => 20,
+            ErrorCode::KeyAlreadyExists => 21,
+            ErrorCode::PreconditionFailed => 22,
+            ErrorCode::TxnConflict => 30,
+            ErrorCode::Custom(code) => *code,
+        }
+    }
+
+    /// Is the error definite?
+    ///
+    /// A definite error means the failed operation did not, and will not, take effect.
+    /// An indefinite error means the operation may, or may not, have taken effect.
+    /// Custom codes are conservatively considered indefinite.
+    pub fn is_definite(&self) -> bool {
+        !matches!(
+            self,
+            ErrorCode::Timeout | ErrorCode::Crash | ErrorCode::Custom(_)
+        )
+    }
+}
+
+impl From<u64> for ErrorCode {
+    fn from(code: u64) -> Self {
+        match code {
+            0 => ErrorCode::Timeout,
+            1 => ErrorCode::NodeNotFound,
+            10 => ErrorCode::NotSupported,
+            11 => ErrorCode::TemporarilyUnavailable,
+            12 => ErrorCode::MalformedRequest,
+            13 => ErrorCode::Crash,
+            14 => ErrorCode::Abort,
+            20 => ErrorCode::KeyDoesNotExist,
+            21 => ErrorCode::KeyAlreadyExists,
+            22 => ErrorCode::PreconditionFailed,
+            30 => ErrorCode::TxnConflict,
+            code => ErrorCode::Custom(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for u64 {
+    fn from(code: ErrorCode) -> Self {
+        code.code()
+    }
+}
 
 /// Errors the library may return to the application
 #[derive(Debug)]
 pub enum Error {
     /// A message could not be deserialized
     Deserialize(serde_json::Error),
+    /// A request reuses the `msg_id` of a call still awaiting its reply
+    DuplicateMsgId,
+    /// The [runtime::Runtime]'s IO egress failed
+    Egress(Box<Error>),
     /// Initialization of a [process::Process] failed
-    Initialization(Box<dyn error::Error>),
+    Initialization(Box<Error>),
     /// An IO operation failed
     IO(io::Error),
+    /// The [runtime::Runtime]'s IO ingress failed
+    Ingress(Box<Error>),
     /// The expected deserialized message type does not match the serialized data
     MessageType,
+    /// A request has no `msg_id` to correlate its reply with
+    MissingMsgId,
+    /// The [runtime::Runtime]'s [process::Process] failed
+    Process(Box<Error>),
+    /// A request was answered with an error reply
+    Reply(msg::Error),
     /// A message could not be serialized
     Serialize(serde_json::Error),
     /// The runtime has shutdown before the process completed
     Shutdown,
     /// Testing only
     TestIO,
+    /// No reply to a request was received in time
+    Timeout,
     /// A process received a message that was unexpected for the current state or protocol
     UnexpectedMsg { expected: &'static str },
 }
@@ -71,6 +208,12 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+impl From<msg::Error> for Error {
+    fn from(e: msg::Error) -> Self {
+        Reply(e)
+    }
+}
+
 impl From<RecvError> for Error {
     fn from(_: RecvError) -> Self {
         Shutdown
@@ -87,6 +230,17 @@ where
     }
 }
 
+#[cfg(any(feature = "async-std", feature = "tokio"))]
+impl<W, A> From<SendError<Event<W, A>>> for Error
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    fn from(_: SendError<Event<W, A>>) -> Self {
+        Shutdown
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -106,3 +260,55 @@ pub type Key = Value;
 
 /// Maelstrom [Lin-kv workload value](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-lin-kv)
 pub type Val = Value;
+
+#[test]
+fn error_code_numeric_identity() {
+    for code in (0..40).chain([1000, 1001]) {
+        assert_eq!(ErrorCode::from(code).code(), code);
+    }
+    assert_eq!(ErrorCode::from(20), ErrorCode::KeyDoesNotExist);
+    assert_eq!(ErrorCode::from(1000), ErrorCode::Custom(1000));
+    assert_eq!(
+        serde_json::to_string(&ErrorCode::PreconditionFailed).expect("JSON"),
+        "22"
+    );
+    assert_eq!(
+        serde_json::from_str::<ErrorCode>("30").expect("code"),
+        ErrorCode::TxnConflict
+    );
+}
+
+#[test]
+fn error_code_custom_known_code() {
+    assert_eq!(ErrorCode::custom(11), ErrorCode::TemporarilyUnavailable);
+    assert_eq!(ErrorCode::custom(1000), ErrorCode::Custom(1000));
+    let code = ErrorCode::custom(11);
+    let json = serde_json::to_string(&code).expect("JSON");
+    assert_eq!(json, "11");
+    assert_eq!(
+        serde_json::from_str::<ErrorCode>(&json).expect("code"),
+        code
+    );
+    // A known code wrapped in Custom keeps its number but not its variant
+    let json = serde_json::to_string(&ErrorCode::Custom(11)).expect("JSON");
+    assert_eq!(
+        serde_json::from_str::<ErrorCode>(&json).expect("code"),
+        ErrorCode::TemporarilyUnavailable
+    );
+}
+
+#[test]
+fn error_code_is_definite() {
+    assert!(!ErrorCode::Timeout.is_definite());
+    assert!(!ErrorCode::Crash.is_definite());
+    assert!(!ErrorCode::Custom(1000).is_definite());
+    assert!(ErrorCode::NodeNotFound.is_definite());
+    assert!(ErrorCode::NotSupported.is_definite());
+    assert!(ErrorCode::TemporarilyUnavailable.is_definite());
+    assert!(ErrorCode::MalformedRequest.is_definite());
+    assert!(ErrorCode::Abort.is_definite());
+    assert!(ErrorCode::KeyDoesNotExist.is_definite());
+    assert!(ErrorCode::KeyAlreadyExists.is_definite());
+    assert!(ErrorCode::PreconditionFailed.is_definite());
+    assert!(ErrorCode::TxnConflict.is_definite());
+}
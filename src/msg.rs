@@ -25,14 +25,23 @@
 //!     send(response);
 //! }
 //! ```
-use std::fmt::Debug;
+//!
+//! or, addressed and in reply to the request, with [Msg::reply]
+//! ```ignore
+//! let response = request.reply(|in_reply_to| Echo(EchoOk { in_reply_to, msg_id: Some(5), echo }))?;
+//! ```
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::SeqCst;
 
-#[cfg(test)]
-use serde::de::DeserializeOwned;
-use serde::{Deserialize, Serialize};
+use serde::de::value::MapDeserializer;
+use serde::de::Error as _;
+use serde::de::{DeserializeOwned, Expected, Unexpected};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[cfg(test)]
 use serde_json::json;
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 #[cfg(test)]
 use crate::msg::Body::Application;
@@ -54,6 +63,10 @@ use crate::{ErrorCode, Id};
 /// The workload parameter is required to disambiguate Maelstrom messages when deserializing into the
 /// target Rust type.
 ///
+/// Unknown envelope and body fields, including the envelope `id`, are dropped on deserialization.
+/// Wrap a type in [Extensible] to capture them and re-emit them on serialization, e.g.
+/// `Extensible<Msg<Extensible<Echo>, A>>`.
+///
 /// Parameters
 /// - `W` the workload body type, e.g. [Echo]
 /// - `A` the application body type
@@ -64,6 +77,68 @@ pub struct Msg<W, A> {
     pub body: Body<W, A>,
 }
 
+impl<W, A> Msg<W, A>
+where
+    W: MsgBody,
+    A: MsgBody,
+{
+    /// Get the body's `msg_id`
+    ///
+    /// Return [None] IFF the body has no `msg_id`, i.e. the sender does not expect a reply
+    pub fn msg_id(&self) -> Option<MsgId> {
+        self.body.msg_id()
+    }
+
+    /// Create a reply to this message
+    ///
+    /// The reply is sent from this message's destination to its source. Its body is created by
+    /// `body`, given this message's `msg_id` for the reply's `in_reply_to`, e.g.
+    /// ```
+    /// # use async_maelstrom::msg::Body::Workload;
+    /// # use async_maelstrom::msg::Echo::{Echo, EchoOk};
+    /// # use async_maelstrom::msg::Msg;
+    /// # let echo = serde_json::json!("boo!");
+    /// # let request: Msg<_, ()> = Msg {
+    /// #     src: "c1".to_string(),
+    /// #     dest: "n1".to_string(),
+    /// #     body: Workload(Echo { msg_id: 1, echo: echo.clone() }),
+    /// # };
+    /// let reply = request.reply(|in_reply_to| Workload(EchoOk { in_reply_to, msg_id: None, echo }))?;
+    /// # assert_eq!(reply.dest, "c1");
+    /// # Ok::<(), async_maelstrom::Error>(())
+    /// ```
+    ///
+    /// Return
+    /// - [Ok] the reply
+    /// - [Err]:[crate::Error::MissingMsgId] IFF this message has no `msg_id`
+    pub fn reply(&self, body: impl FnOnce(MsgId) -> Body<W, A>) -> crate::Result<Msg<W, A>> {
+        let in_reply_to = self.msg_id().ok_or(crate::Error::MissingMsgId)?;
+        Ok(Msg {
+            src: self.dest.clone(),
+            dest: self.src.clone(),
+            body: body(in_reply_to),
+        })
+    }
+
+    /// Create an [Error] reply to this message
+    ///
+    /// See [Self::reply].
+    pub fn reply_error(
+        &self,
+        code: ErrorCode,
+        text: impl Into<String>,
+    ) -> crate::Result<Msg<W, A>> {
+        let text = text.into();
+        self.reply(|in_reply_to| {
+            Body::Error(Error {
+                in_reply_to,
+                code,
+                text,
+            })
+        })
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
 #[serde(untagged)]
 pub enum Body<W, A> {
@@ -71,10 +146,10 @@ pub enum Body<W, A> {
     ///
     /// From the Maelstrom [message documentation](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#messages)
     /// > Messages exchanged between your server nodes may have any body
-    ///   structure you like; you are not limited to request-response, and may
-    ///   invent any message semantics you choose. If some of your messages do
-    ///   use the body format described above, Maelstrom can help generate useful
-    ///   visualizations and statistics for those messages.
+    /// > structure you like; you are not limited to request-response, and may
+    /// > invent any message semantics you choose. If some of your messages do
+    /// > use the body format described above, Maelstrom can help generate useful
+    /// > visualizations and statistics for those messages.
     Application(A),
     Error(Error),
     Init(Init),
@@ -120,7 +195,110 @@ pub enum Init {
     InitOk { in_reply_to: MsgId, msg_id: MsgId },
 }
 
+/// Maelstrom [G-set workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-g-set)
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum GSet {
+    #[serde(rename = "add")]
+    Add { msg_id: MsgId, element: Val },
+    #[serde(rename = "add_ok")]
+    AddOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+    },
+    #[serde(rename = "read")]
+    Read { msg_id: MsgId },
+    #[serde(rename = "read_ok")]
+    ReadOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        value: Vec<Val>,
+    },
+}
+
+/// Maelstrom [Kafka workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-kafka)
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum Kafka {
+    #[serde(rename = "send")]
+    Send {
+        msg_id: MsgId,
+        key: LogKey,
+        msg: Val,
+    },
+    #[serde(rename = "send_ok")]
+    SendOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        offset: Offset,
+    },
+    #[serde(rename = "poll")]
+    Poll {
+        msg_id: MsgId,
+        offsets: HashMap<LogKey, Offset>,
+    },
+    #[serde(rename = "poll_ok")]
+    PollOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        msgs: HashMap<LogKey, Vec<LogRecord>>,
+    },
+    #[serde(rename = "commit_offsets")]
+    CommitOffsets {
+        msg_id: MsgId,
+        offsets: HashMap<LogKey, Offset>,
+    },
+    #[serde(rename = "commit_offsets_ok")]
+    CommitOffsetsOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+    },
+    #[serde(rename = "list_committed_offsets")]
+    ListCommittedOffsets { msg_id: MsgId, keys: Vec<LogKey> },
+    #[serde(rename = "list_committed_offsets_ok")]
+    ListCommittedOffsetsOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        offsets: HashMap<LogKey, Offset>,
+    },
+}
+
+/// A [Kafka workload](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-kafka)
+/// log record
+///
+/// Records are encoded as `[offset, msg]` arrays.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogRecord {
+    pub offset: Offset,
+    pub msg: Val,
+}
+
+impl Serialize for LogRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.offset, &self.msg).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogRecord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (offset, msg) = Deserialize::deserialize(deserializer)?;
+        Ok(LogRecord { offset, msg })
+    }
+}
+
 /// Maelstrom [Lin-kv workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-lin-kv)
+///
+/// The same messages are used to talk to Maelstrom's built-in
+/// [key-value services](https://github.com/jepsen-io/maelstrom/blob/main/doc/services.md)
+/// [LIN_KV], [SEQ_KV] and [LWW_KV]. The services reply to failed requests with a
+/// [Body::Error], e.g. [ErrorCode::KeyDoesNotExist] for a read or cas of a missing key, and
+/// [ErrorCode::PreconditionFailed] for a cas whose `from` value does not match.
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
 #[serde(tag = "type")]
 pub enum LinKv {
@@ -130,6 +308,9 @@ pub enum LinKv {
         key: Key,
         from: Val,
         to: Val,
+        /// Create the key with the `to` value if it does not exist, rather than failing
+        #[serde(skip_serializing_if = "Option::is_none")]
+        create_if_not_exists: Option<bool>,
     },
     #[serde(rename = "cas_ok")]
     CasOk {
@@ -149,15 +330,120 @@ pub enum LinKv {
     #[serde(rename = "write")]
     Write { msg_id: MsgId, key: Key, value: Val },
     #[serde(rename = "write_ok")]
-    WriteOk { in_reply_to: MsgId },
+    WriteOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+    },
+}
+
+/// Maelstrom [Seq-kv service messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/services.md#seq-kv)
+pub type SeqKv = LinKv;
+
+/// Maelstrom [Lww-kv service messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/services.md#lww-kv)
+pub type LwwKv = LinKv;
+
+/// Node ID of Maelstrom's linearizable [key-value service](https://github.com/jepsen-io/maelstrom/blob/main/doc/services.md#lin-kv)
+pub const LIN_KV: &str = "lin-kv";
+
+/// Node ID of Maelstrom's sequentially consistent [key-value service](https://github.com/jepsen-io/maelstrom/blob/main/doc/services.md#seq-kv)
+pub const SEQ_KV: &str = "seq-kv";
+
+/// Node ID of Maelstrom's last-write-wins [key-value service](https://github.com/jepsen-io/maelstrom/blob/main/doc/services.md#lww-kv)
+pub const LWW_KV: &str = "lww-kv";
+
+/// Maelstrom [Txn-rw-register workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-txn-rw-register)
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum TxnRwRegister {
+    #[serde(rename = "txn")]
+    Txn {
+        msg_id: MsgId,
+        txn: Vec<RwRegisterOp>,
+    },
+    #[serde(rename = "txn_ok")]
+    TxnOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        txn: Vec<RwRegisterOp>,
+    },
+}
+
+/// [Txn-rw-register workload](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-txn-rw-register)
+/// micro-operation
+///
+/// Micro-operations are encoded as `["r", key, value]` and `["w", key, value]` arrays.
+/// A read's `value` is `None` in a request, and the value read, if any, in a response.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RwRegisterOp {
+    Read { key: Key, value: Option<Val> },
+    Write { key: Key, value: Val },
+}
+
+impl Serialize for RwRegisterOp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            RwRegisterOp::Read { key, value } => ("r", key, value).serialize(serializer),
+            RwRegisterOp::Write { key, value } => ("w", key, value).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RwRegisterOp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (f, key, value): (String, Key, Value) = Deserialize::deserialize(deserializer)?;
+        match f.as_str() {
+            "r" => Ok(RwRegisterOp::Read {
+                key,
+                value: match value {
+                    Value::Null => None,
+                    value => Some(value),
+                },
+            }),
+            "w" => Ok(RwRegisterOp::Write { key, value }),
+            _ => Err(D::Error::unknown_variant(&f, &["r", "w"])),
+        }
+    }
+}
+
+/// Maelstrom [G-counter workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-g-counter)
+///
+/// A grow-only counter only accepts non-negative deltas.
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum GCounter {
+    #[serde(rename = "add")]
+    Add { msg_id: MsgId, delta: u64 },
+    #[serde(rename = "add_ok")]
+    AddOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+    },
+    #[serde(rename = "read")]
+    Read { msg_id: MsgId },
+    #[serde(rename = "read_ok")]
+    ReadOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        value: u64,
+    },
 }
 
-/// Maelstrom [Lin-kv workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-pn-counter)
+/// Maelstrom [PN-counter workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-pn-counter)
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
 #[serde(tag = "type")]
 pub enum PnCounter {
     #[serde(rename = "add")]
     Add { msg_id: MsgId, delta: i64 },
+    #[serde(rename = "add_ok")]
+    AddOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+    },
     #[serde(rename = "read")]
     Read { msg_id: MsgId },
     #[serde(rename = "read_ok")]
@@ -169,154 +455,1369 @@ pub enum PnCounter {
     },
 }
 
-/// Maelstrom [message ID](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#message-bodies)
-pub type MsgId = u64;
+/// Maelstrom [Broadcast workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-broadcast)
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum Broadcast {
+    #[serde(rename = "broadcast")]
+    Broadcast { msg_id: MsgId, message: Val },
+    #[serde(rename = "broadcast_ok")]
+    BroadcastOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+    },
+    #[serde(rename = "read")]
+    Read { msg_id: MsgId },
+    #[serde(rename = "read_ok")]
+    ReadOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        messages: Vec<Val>,
+    },
+    #[serde(rename = "topology")]
+    Topology {
+        msg_id: MsgId,
+        topology: HashMap<Id, Vec<Id>>,
+    },
+    #[serde(rename = "topology_ok")]
+    TopologyOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+    },
+}
 
-/// Maelstrom [Lin-kv workload key](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-lin-kv)
-pub type Key = Value;
+/// Maelstrom [Txn-list-append workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-txn-list-append)
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum TxnListAppend {
+    #[serde(rename = "txn")]
+    Txn {
+        msg_id: MsgId,
+        txn: Vec<ListAppendOp>,
+    },
+    #[serde(rename = "txn_ok")]
+    TxnOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        txn: Vec<ListAppendOp>,
+    },
+}
 
-/// Maelstrom [Lin-kv workload value](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-lin-kv)
-pub type Val = Value;
+/// [Txn-list-append workload](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-txn-list-append)
+/// micro-operation
+///
+/// Micro-operations are encoded as `["r", key, value]` and `["append", key, element]` arrays.
+/// A read's `value` is `None` in a request, and the list read, if any, in a response.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ListAppendOp {
+    Read { key: Key, value: Option<Vec<Val>> },
+    Append { key: Key, element: Val },
+}
+
+impl Serialize for ListAppendOp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ListAppendOp::Read { key, value } => ("r", key, value).serialize(serializer),
+            ListAppendOp::Append { key, element } => ("append", key, element).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ListAppendOp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (f, key, value): (String, Key, Value) = Deserialize::deserialize(deserializer)?;
+        match f.as_str() {
+            "r" => Ok(ListAppendOp::Read {
+                key,
+                value: Deserialize::deserialize(value).map_err(D::Error::custom)?,
+            }),
+            "append" => Ok(ListAppendOp::Append {
+                key,
+                element: value,
+            }),
+            _ => Err(D::Error::unknown_variant(&f, &["r", "append"])),
+        }
+    }
+}
+
+/// Maelstrom [Unique-ids workload messages](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-unique-ids)
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum UniqueIds {
+    #[serde(rename = "generate")]
+    Generate { msg_id: MsgId },
+    #[serde(rename = "generate_ok")]
+    GenerateOk {
+        in_reply_to: MsgId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        msg_id: Option<MsgId>,
+        id: Val,
+    },
+}
+
+/// A message, or message body, that preserves unknown fields
+///
+/// Fields of the serialized object that are not part of `T`'s serialization are captured in
+/// `extra` when deserializing, and re-emitted when serializing. This allows proxies and
+/// journaling tools to pass messages on without losing information.
+///
+/// Parameters
+/// - `T` a type serialized as a JSON object, e.g. [Msg], [Echo] or an application body type
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Extensible<T> {
+    pub inner: T,
+    /// Fields unknown to `T`
+    pub extra: Map<String, Value>,
+}
+
+impl<T> Extensible<T> {
+    /// Create a value without extra fields
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            extra: Map::new(),
+        }
+    }
+}
+
+impl<W, A> Extensible<Msg<W, A>> {
+    /// Get the envelope ID assigned by Maelstrom, if any
+    pub fn id(&self) -> Option<u64> {
+        self.extra.get("id").and_then(Value::as_u64)
+    }
+}
+
+impl<T> From<T> for Extensible<T> {
+    fn from(inner: T) -> Self {
+        Self::new(inner)
+    }
+}
+
+impl<T: Serialize> Serialize for Extensible<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error as _;
+        match serde_json::to_value(&self.inner).map_err(S::Error::custom)? {
+            Value::Object(mut fields) => {
+                for (k, v) in &self.extra {
+                    fields.entry(k.clone()).or_insert_with(|| v.clone());
+                }
+                fields.serialize(serializer)
+            }
+            _ => Err(S::Error::custom("expected an object")),
+        }
+    }
+}
+
+impl<'de, T: Serialize + DeserializeOwned> Deserialize<'de> for Extensible<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut extra = Map::deserialize(deserializer)?;
+        let inner: T = serde_json::from_value(Value::Object(extra.clone())).map_err(|e| {
+            // Report an unknown type as such, as `T` would
+            match (body_types::<T>(), extra.get("type").and_then(Value::as_str)) {
+                (BodyTypes::Tagged(names), Some(t)) if !names.contains(&t) => {
+                    D::Error::unknown_variant(t, names)
+                }
+                _ => D::Error::custom(e),
+            }
+        })?;
+        if let Value::Object(known) = serde_json::to_value(&inner).map_err(D::Error::custom)? {
+            extra.retain(|k, _| !known.contains_key(k));
+        }
+        Ok(Self { inner, extra })
+    }
+}
+
+/// Common [message body](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#message-bodies) fields
+///
+/// Implemented by all library bodies, and by [Body] and [Extensible] for bodies that implement
+/// it. Replying to a message and calling a service read these fields, so application body types
+/// used to do so opt in by implementing the trait, e.g.
+/// ```
+/// # use async_maelstrom::msg::{MsgBody, MsgId};
+/// #[derive(serde::Serialize)]
+/// #[serde(tag = "type", rename = "gossip")]
+/// struct Gossip {
+///     msg_id: MsgId,
+/// }
+///
+/// impl MsgBody for Gossip {}
+/// # assert_eq!(Gossip { msg_id: 3 }.msg_id(), Some(3));
+/// # assert_eq!(Gossip { msg_id: 3 }.type_name().as_deref(), Some("gossip"));
+/// ```
+///
+/// The provided methods read the fields of the body's serialization. They serialize the whole
+/// body to do so, so bodies with large payloads may override them.
+pub trait MsgBody: Serialize {
+    /// Get the body's `msg_id`, if any
+    fn msg_id(&self) -> Option<MsgId> {
+        serialized_field(self, "msg_id").and_then(|v| v.as_u64())
+    }
+
+    /// Get the body's `in_reply_to`, if any
+    fn in_reply_to(&self) -> Option<MsgId> {
+        serialized_field(self, "in_reply_to").and_then(|v| v.as_u64())
+    }
+
+    /// Get the body's wire `type`, if any
+    fn type_name(&self) -> Option<String> {
+        match serialized_field(self, "type") {
+            Some(Value::String(type_name)) => Some(type_name),
+            _ => None,
+        }
+    }
+}
+
+impl<W: MsgBody, A: MsgBody> MsgBody for Body<W, A> {
+    fn msg_id(&self) -> Option<MsgId> {
+        match self {
+            Body::Application(a) => a.msg_id(),
+            Body::Error(e) => e.msg_id(),
+            Body::Init(i) => i.msg_id(),
+            Body::Workload(w) => w.msg_id(),
+        }
+    }
+
+    fn in_reply_to(&self) -> Option<MsgId> {
+        match self {
+            Body::Application(a) => a.in_reply_to(),
+            Body::Error(e) => e.in_reply_to(),
+            Body::Init(i) => i.in_reply_to(),
+            Body::Workload(w) => w.in_reply_to(),
+        }
+    }
+
+    fn type_name(&self) -> Option<String> {
+        match self {
+            Body::Application(a) => a.type_name(),
+            Body::Error(e) => e.type_name(),
+            Body::Init(i) => i.type_name(),
+            Body::Workload(w) => w.type_name(),
+        }
+    }
+}
+
+impl<T: MsgBody> MsgBody for Extensible<T> {
+    fn msg_id(&self) -> Option<MsgId> {
+        self.inner.msg_id()
+    }
+
+    fn in_reply_to(&self) -> Option<MsgId> {
+        self.inner.in_reply_to()
+    }
+
+    fn type_name(&self) -> Option<String> {
+        self.inner.type_name()
+    }
+}
+
+/// The empty application body
+impl MsgBody for () {
+    fn msg_id(&self) -> Option<MsgId> {
+        None
+    }
+
+    fn in_reply_to(&self) -> Option<MsgId> {
+        None
+    }
+
+    fn type_name(&self) -> Option<String> {
+        None
+    }
+}
+
+impl MsgBody for Error {
+    fn msg_id(&self) -> Option<MsgId> {
+        None
+    }
+
+    fn in_reply_to(&self) -> Option<MsgId> {
+        Some(self.in_reply_to)
+    }
+
+    fn type_name(&self) -> Option<String> {
+        Some("error".to_string())
+    }
+}
+
+impl MsgBody for Init {
+    fn msg_id(&self) -> Option<MsgId> {
+        match self {
+            Init::Init { msg_id, .. } | Init::InitOk { msg_id, .. } => Some(*msg_id),
+        }
+    }
+
+    fn in_reply_to(&self) -> Option<MsgId> {
+        match self {
+            Init::Init { .. } => None,
+            Init::InitOk { in_reply_to, .. } => Some(*in_reply_to),
+        }
+    }
+
+    fn type_name(&self) -> Option<String> {
+        Some(
+            match self {
+                Init::Init { .. } => "init",
+                Init::InitOk { .. } => "init_ok",
+            }
+            .to_string(),
+        )
+    }
+}
+
+/// Get the field `key` of the serialized `body`
+///
+/// Return [None] IFF the body does not serialize to an object with the field
+fn serialized_field<T: Serialize + ?Sized>(body: &T, key: &str) -> Option<Value> {
+    match serde_json::to_value(body).ok()? {
+        Value::Object(mut fields) => fields.remove(key),
+        _ => None,
+    }
+}
+
+/// The wire `type`s of a body type
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum BodyTypes {
+    /// The variant names of a `type`-tagged enum
+    Tagged(&'static [&'static str]),
+    /// The body type is not deserialized from an object, e.g. `()`, so has no types
+    Empty,
+    /// The types cannot be determined, e.g. for untagged enums and structs
+    Unknown,
+}
+
+impl BodyTypes {
+    /// Is `t` definitely not one of the types?
+    pub(crate) fn excludes(&self, t: &str) -> bool {
+        match self {
+            BodyTypes::Tagged(names) => !names.contains(&t),
+            BodyTypes::Empty => true,
+            BodyTypes::Unknown => false,
+        }
+    }
+}
+
+/// Get the wire `type`s of the body type `T`
+///
+/// The types of a `type`-tagged enum, including one wrapped in [Extensible], are reported by its
+/// derived [Deserialize] when given an unknown type.
+pub(crate) fn body_types<T: DeserializeOwned>() -> BodyTypes {
+    let body = MapDeserializer::<_, TypeProbe>::new(std::iter::once(("type", "\0")));
+    match T::deserialize(body) {
+        Err(TypeProbe(types)) => types,
+        Ok(_) => BodyTypes::Unknown,
+    }
+}
+
+/// Deserialization error capturing the [BodyTypes] expected by a body type
+#[derive(Debug)]
+struct TypeProbe(BodyTypes);
+
+impl Display for TypeProbe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {:?}", self.0)
+    }
+}
+
+impl std::error::Error for TypeProbe {}
+
+impl serde::de::Error for TypeProbe {
+    fn custom<T: Display>(_msg: T) -> Self {
+        TypeProbe(BodyTypes::Unknown)
+    }
+
+    fn invalid_type(unexp: Unexpected, _exp: &dyn Expected) -> Self {
+        match unexp {
+            Unexpected::Map => TypeProbe(BodyTypes::Empty),
+            _ => TypeProbe(BodyTypes::Unknown),
+        }
+    }
+
+    fn unknown_variant(_variant: &str, expected: &'static [&'static str]) -> Self {
+        TypeProbe(BodyTypes::Tagged(expected))
+    }
+}
+
+/// Implement [MsgBody] for a workload body enum
+///
+/// Request variants have a `msg_id`. Response variants have an `in_reply_to`, and an optional
+/// `msg_id`. Each variant is listed with its wire `type`.
+macro_rules! impl_workload_msg_body {
+    (
+        $body:ident,
+        requests { $($req:ident => $req_type:literal),* $(,)? },
+        responses { $($rsp:ident => $rsp_type:literal),* $(,)? }
+    ) => {
+        impl MsgBody for $body {
+            fn msg_id(&self) -> Option<MsgId> {
+                match self {
+                    $($body::$req { msg_id, .. } => Some(*msg_id),)*
+                    $($body::$rsp { msg_id, .. } => *msg_id,)*
+                }
+            }
+
+            fn in_reply_to(&self) -> Option<MsgId> {
+                match self {
+                    $($body::$req { .. } => None,)*
+                    $($body::$rsp { in_reply_to, .. } => Some(*in_reply_to),)*
+                }
+            }
+
+            fn type_name(&self) -> Option<String> {
+                let type_name = match self {
+                    $($body::$req { .. } => $req_type,)*
+                    $($body::$rsp { .. } => $rsp_type,)*
+                };
+                Some(type_name.to_string())
+            }
+        }
+    };
+}
+
+impl_workload_msg_body!(
+    Echo,
+    requests { Echo => "echo" },
+    responses { EchoOk => "echo_ok" }
+);
+impl_workload_msg_body!(
+    GSet,
+    requests { Add => "add", Read => "read" },
+    responses { AddOk => "add_ok", ReadOk => "read_ok" }
+);
+impl_workload_msg_body!(
+    Kafka,
+    requests {
+        Send => "send",
+        Poll => "poll",
+        CommitOffsets => "commit_offsets",
+        ListCommittedOffsets => "list_committed_offsets",
+    },
+    responses {
+        SendOk => "send_ok",
+        PollOk => "poll_ok",
+        CommitOffsetsOk => "commit_offsets_ok",
+        ListCommittedOffsetsOk => "list_committed_offsets_ok",
+    }
+);
+impl_workload_msg_body!(
+    LinKv,
+    requests { Cas => "cas", Read => "read", Write => "write" },
+    responses { CasOk => "cas_ok", ReadOk => "read_ok", WriteOk => "write_ok" }
+);
+impl_workload_msg_body!(
+    TxnRwRegister,
+    requests { Txn => "txn" },
+    responses { TxnOk => "txn_ok" }
+);
+impl_workload_msg_body!(
+    GCounter,
+    requests { Add => "add", Read => "read" },
+    responses { AddOk => "add_ok", ReadOk => "read_ok" }
+);
+impl_workload_msg_body!(
+    PnCounter,
+    requests { Add => "add", Read => "read" },
+    responses { AddOk => "add_ok", ReadOk => "read_ok" }
+);
+impl_workload_msg_body!(
+    Broadcast,
+    requests { Broadcast => "broadcast", Read => "read", Topology => "topology" },
+    responses { BroadcastOk => "broadcast_ok", ReadOk => "read_ok", TopologyOk => "topology_ok" }
+);
+impl_workload_msg_body!(
+    TxnListAppend,
+    requests { Txn => "txn" },
+    responses { TxnOk => "txn_ok" }
+);
+impl_workload_msg_body!(
+    UniqueIds,
+    requests { Generate => "generate" },
+    responses { GenerateOk => "generate_ok" }
+);
+
+/// Maelstrom [message ID](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#message-bodies)
+pub type MsgId = u64;
+
+/// Generator of unique [MsgId]s
+///
+/// The [Runtime](crate::runtime::Runtime) owns a generator that it shares with its process via
+/// [ProcNet::next_msg_id](crate::process::ProcNet::next_msg_id), so the IDs used by the runtime
+/// and the process never collide.
+#[derive(Debug, Default)]
+pub struct MsgIdGenerator {
+    next: AtomicU64,
+}
+
+impl MsgIdGenerator {
+    /// Create a generator whose first ID is `start`
+    pub fn new(start: MsgId) -> Self {
+        Self {
+            next: AtomicU64::new(start),
+        }
+    }
+
+    /// Get the next unique message ID
+    pub fn next_msg_id(&self) -> MsgId {
+        self.next.fetch_add(1, SeqCst)
+    }
+
+    /// Peek at the next message ID without using it
+    #[cfg(test)]
+    fn peek(&self) -> MsgId {
+        self.next.load(SeqCst)
+    }
+}
+
+/// Maelstrom [Kafka workload log key](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-kafka)
+pub type LogKey = String;
+
+/// Maelstrom [Kafka workload log offset](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-kafka)
+pub type Offset = u64;
+
+/// Maelstrom [Lin-kv workload key](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-lin-kv)
+pub type Key = Value;
+
+/// Maelstrom [Lin-kv workload value](https://github.com/jepsen-io/maelstrom/blob/main/doc/workloads.md#workload-lin-kv)
+pub type Val = Value;
+
+#[test]
+fn serde_broadcast_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"broadcast","message":1000,"msg_id":1},"src":"c11","id":11}"#;
+    let msg: Msg<Broadcast, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body: Workload(Broadcast::Broadcast { msg_id, message }),
+    } = &msg
+    {
+        assert_eq!(dest, "n1");
+        assert_eq!(src, "c11");
+        assert_eq!(message, &json!(1000));
+        assert_eq!(*msg_id, 1);
+    } else {
+        panic!("expected broadcast message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_broadcast_ok_msg() {
+    let buf = r#"{"dest":"c11","body":{"type":"broadcast_ok","in_reply_to":1},"src":"n1"}"#;
+    let msg: Msg<Broadcast, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body:
+            Workload(Broadcast::BroadcastOk {
+                in_reply_to,
+                msg_id,
+            }),
+    } = &msg
+    {
+        assert_eq!(dest, "c11");
+        assert_eq!(src, "n1");
+        assert_eq!(*in_reply_to, 1);
+        assert_eq!(msg_id, &None);
+    } else {
+        panic!("expected broadcast_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_broadcast_read_ok_msg() {
+    let buf = r#"{"dest":"c11","body":{"type":"read_ok","messages":[1,8,72,25],"msg_id":3,"in_reply_to":2},"src":"n1"}"#;
+    let msg: Msg<Broadcast, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body:
+            Workload(Broadcast::ReadOk {
+                in_reply_to,
+                msg_id,
+                messages,
+            }),
+    } = &msg
+    {
+        assert_eq!(dest, "c11");
+        assert_eq!(src, "n1");
+        assert_eq!(messages, &vec![json!(1), json!(8), json!(72), json!(25)]);
+        assert_eq!(msg_id, &Some(3));
+        assert_eq!(*in_reply_to, 2);
+    } else {
+        panic!("expected read_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_topology_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"topology","topology":{"n1":["n2","n3"],"n2":["n1"],"n3":["n1"]},"msg_id":1},"src":"c4","id":4}"#;
+    let msg: Msg<Broadcast, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body: Workload(Broadcast::Topology { msg_id, topology }),
+    } = &msg
+    {
+        assert_eq!(dest, "n1");
+        assert_eq!(src, "c4");
+        assert_eq!(topology.len(), 3);
+        assert_eq!(topology["n1"], vec!["n2".to_string(), "n3".to_string()]);
+        assert_eq!(topology["n3"], vec!["n1".to_string()]);
+        assert_eq!(*msg_id, 1);
+    } else {
+        panic!("expected topology message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_topology_ok_msg() {
+    let buf =
+        r#"{"dest":"c4","body":{"type":"topology_ok","in_reply_to":1,"msg_id":2},"src":"n1"}"#;
+    let msg: Msg<Broadcast, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body:
+            Workload(Broadcast::TopologyOk {
+                in_reply_to,
+                msg_id,
+            }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*in_reply_to, 1);
+        assert_eq!(msg_id, &Some(2));
+    } else {
+        panic!("expected topology_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_cas_msg() {
+    let buf = r#"{"dest":"n1","body":{"key":0,"from":4,"to":2,"type":"cas","msg_id":1},"src":"c11","id":11}"#;
+    let msg: Msg<LinKv, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body:
+            Workload(LinKv::Cas {
+                msg_id,
+                key,
+                from,
+                to,
+                create_if_not_exists,
+            }),
+    } = &msg
+    {
+        assert_eq!(dest, "n1");
+        assert_eq!(src, "c11");
+        assert_eq!(key, &json!(0));
+        assert_eq!(from, &json!(4));
+        assert_eq!(to, &json!(2));
+        assert_eq!(*msg_id, 1);
+        assert_eq!(create_if_not_exists, &None);
+    } else {
+        panic!("expected cas message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_cas_create_if_not_exists_msg() {
+    let buf = r#"{"dest":"lin-kv","body":{"key":"k","from":null,"to":2,"create_if_not_exists":true,"type":"cas","msg_id":7},"src":"n1"}"#;
+    let msg: Msg<LinKv, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        dest,
+        body: Workload(LinKv::Cas {
+            create_if_not_exists,
+            ..
+        }),
+        ..
+    } = &msg
+    {
+        assert_eq!(dest, LIN_KV);
+        assert_eq!(create_if_not_exists, &Some(true));
+    } else {
+        panic!("expected cas message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_kv_service_error_msg() {
+    for (buf, expected) in [
+        (
+            r#"{"src":"seq-kv","dest":"n1","body":{"type":"error","in_reply_to":3,"code":20,"text":"key does not exist"}}"#,
+            ErrorCode::KeyDoesNotExist,
+        ),
+        (
+            r#"{"src":"lww-kv","dest":"n1","body":{"type":"error","in_reply_to":3,"code":22,"text":"expected 1, but had 2"}}"#,
+            ErrorCode::PreconditionFailed,
+        ),
+    ] {
+        let msg: Msg<SeqKv, ()> = serde_json::from_str(buf).expect("message");
+        if let Msg {
+            body: Body::Error(Error {
+                in_reply_to, code, ..
+            }),
+            ..
+        } = &msg
+        {
+            assert_eq!(*in_reply_to, 3);
+            assert_eq!(*code, expected);
+        } else {
+            panic!("expected error message, got {:?}", msg)
+        }
+        assert_serde_preserves_identity(&msg);
+    }
+}
+
+#[test]
+fn serde_write_ok_msg() {
+    let buf =
+        r#"{"src":"lin-kv","dest":"n1","body":{"type":"write_ok","in_reply_to":4,"msg_id":9}}"#;
+    let msg: Msg<LinKv, ()> = serde_json::from_str(buf).expect("message");
+    assert!(matches!(
+        msg.body,
+        Workload(LinKv::WriteOk {
+            in_reply_to: 4,
+            msg_id: Some(9)
+        })
+    ));
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_cas_ok_msg() {
+    let buf = r#"{"dest":"n1","body":{ "type": "cas_ok", "in_reply_to": 1 },"src":"c11","id":11}"#;
+    let msg: Msg<LinKv, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body: Workload(LinKv::CasOk {
+            in_reply_to,
+            msg_id,
+        }),
+    } = &msg
+    {
+        assert_eq!(dest, "n1");
+        assert_eq!(src, "c11");
+        assert_eq!(*in_reply_to, 1);
+        assert_eq!(msg_id, &None);
+    } else {
+        panic!("expected cas_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_echo_msg() {
+    let buf = r#"{"dest":"n1","body":{"echo":"Please echo 36","type":"echo","msg_id":1},"src":"c10","id":10}"#;
+    let msg: Msg<Echo, ()> = serde_json::from_str(buf).expect("echo message");
+    if let Msg {
+        src,
+        dest,
+        body: Workload(Echo::Echo { msg_id, echo }),
+    } = &msg
+    {
+        assert_eq!(dest, "n1");
+        assert_eq!(src, "c10");
+        assert_eq!(echo, "Please echo 36");
+        assert_eq!(*msg_id, 1);
+    } else {
+        panic!("expected echo message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_generate_msg() {
+    let buf = r#"{"dest":"n3","body":{"type":"generate","msg_id":2},"src":"c1","id":6}"#;
+    let msg: Msg<UniqueIds, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body: Workload(UniqueIds::Generate { msg_id }),
+    } = &msg
+    {
+        assert_eq!(dest, "n3");
+        assert_eq!(src, "c1");
+        assert_eq!(*msg_id, 2);
+    } else {
+        panic!("expected generate message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_generate_ok_msg() {
+    let buf =
+        r#"{"dest":"c1","body":{"type":"generate_ok","id":"n3-17","in_reply_to":2},"src":"n3"}"#;
+    let msg: Msg<UniqueIds, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body:
+            Workload(UniqueIds::GenerateOk {
+                in_reply_to,
+                msg_id,
+                id,
+            }),
+    } = &msg
+    {
+        assert_eq!(dest, "c1");
+        assert_eq!(src, "n3");
+        assert_eq!(id, &json!("n3-17"));
+        assert_eq!(*in_reply_to, 2);
+        assert_eq!(msg_id, &None);
+    } else {
+        panic!("expected generate_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+    let data = serde_json::to_string(&msg.body).expect("JSON data");
+    assert!(!data.contains("msg_id"), "unexpected msg_id in {}", data);
+}
+
+#[test]
+fn serde_gset_add_msg() {
+    let buf = r#"{"dest":"n2","body":{"type":"add","element":5,"msg_id":1},"src":"c7","id":7}"#;
+    let msg: Msg<GSet, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body: Workload(GSet::Add { msg_id, element }),
+    } = &msg
+    {
+        assert_eq!(dest, "n2");
+        assert_eq!(src, "c7");
+        assert_eq!(element, &json!(5));
+        assert_eq!(*msg_id, 1);
+    } else {
+        panic!("expected add message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_gset_add_ok_msg() {
+    let buf = r#"{"dest":"c7","body":{"type":"add_ok","in_reply_to":1},"src":"n2"}"#;
+    let msg: Msg<GSet, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body: Workload(GSet::AddOk {
+            in_reply_to,
+            msg_id,
+        }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*in_reply_to, 1);
+        assert_eq!(msg_id, &None);
+    } else {
+        panic!("expected add_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_gset_read_msg() {
+    let buf = r#"{"dest":"n2","body":{"type":"read","msg_id":2},"src":"c7","id":8}"#;
+    let msg: Msg<GSet, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body: Workload(GSet::Read { msg_id }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*msg_id, 2);
+    } else {
+        panic!("expected read message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_gset_read_ok_msg() {
+    let buf = r#"{"dest":"c7","body":{"type":"read_ok","value":[1,5,9],"msg_id":4,"in_reply_to":2},"src":"n2"}"#;
+    let msg: Msg<GSet, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body:
+            Workload(GSet::ReadOk {
+                in_reply_to,
+                msg_id,
+                value,
+            }),
+    } = &msg
+    {
+        assert_eq!(dest, "c7");
+        assert_eq!(src, "n2");
+        assert_eq!(value, &vec![json!(1), json!(5), json!(9)]);
+        assert_eq!(msg_id, &Some(4));
+        assert_eq!(*in_reply_to, 2);
+    } else {
+        panic!("expected read_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_init_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"init","node_id":"n1","node_ids":["n1","n2","n3","n4","n5"],"msg_id":1},"src":"c4","id":4}"#;
+    let msg: Msg<Echo, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body:
+            Body::Init(Init::Init {
+                msg_id,
+                node_id,
+                node_ids,
+            }),
+    } = &msg
+    {
+        assert_eq!(dest, "n1");
+        assert_eq!(src, "c4");
+        assert_eq!(node_id, "n1");
+        assert_eq!(
+            node_ids,
+            &["n1", "n2", "n3", "n4", "n5"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(*msg_id, 1);
+    } else {
+        panic!("expected init message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_init_ok_msg() {
+    let buf = r#"{"src":"n1","dest":"c4","body":{"type":"init_ok","in_reply_to":1,"msg_id":0}}"#;
+    let msg: Msg<Init, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body: Body::Init(Init::InitOk {
+            in_reply_to,
+            msg_id,
+        }),
+    } = &msg
+    {
+        assert_eq!(dest, "c4");
+        assert_eq!(src, "n1");
+        assert_eq!(*in_reply_to, 1);
+        assert_eq!(*msg_id, 0);
+    } else {
+        panic!("expected init_ok message, got {:?}", msg);
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_kafka_send_msg() {
+    let buf =
+        r#"{"dest":"n1","body":{"type":"send","key":"k1","msg":123,"msg_id":1},"src":"c2","id":2}"#;
+    let msg: Msg<Kafka, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body: Workload(Kafka::Send { msg_id, key, msg }),
+    } = &msg
+    {
+        assert_eq!(dest, "n1");
+        assert_eq!(src, "c2");
+        assert_eq!(key, "k1");
+        assert_eq!(msg, &json!(123));
+        assert_eq!(*msg_id, 1);
+    } else {
+        panic!("expected send message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_kafka_send_ok_msg() {
+    let buf = r#"{"dest":"c2","body":{"type":"send_ok","offset":1000,"in_reply_to":1},"src":"n1"}"#;
+    let msg: Msg<Kafka, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body:
+            Workload(Kafka::SendOk {
+                in_reply_to,
+                msg_id,
+                offset,
+            }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*offset, 1000);
+        assert_eq!(*in_reply_to, 1);
+        assert_eq!(msg_id, &None);
+    } else {
+        panic!("expected send_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_kafka_poll_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"poll","offsets":{"k1":1000,"k2":2000},"msg_id":2},"src":"c2","id":3}"#;
+    let msg: Msg<Kafka, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body: Workload(Kafka::Poll { msg_id, offsets }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*msg_id, 2);
+        assert_eq!(offsets.len(), 2);
+        assert_eq!(offsets["k1"], 1000);
+        assert_eq!(offsets["k2"], 2000);
+    } else {
+        panic!("expected poll message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_kafka_poll_ok_msg() {
+    let buf = r#"{"dest":"c2","body":{"type":"poll_ok","msgs":{"k1":[[1000,9],[1001,5],[1002,15]],"k2":[[2000,7]]},"in_reply_to":2},"src":"n1"}"#;
+    let msg: Msg<Kafka, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body: Workload(Kafka::PollOk {
+            in_reply_to, msgs, ..
+        }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*in_reply_to, 2);
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(
+            msgs["k1"],
+            vec![
+                LogRecord {
+                    offset: 1000,
+                    msg: json!(9)
+                },
+                LogRecord {
+                    offset: 1001,
+                    msg: json!(5)
+                },
+                LogRecord {
+                    offset: 1002,
+                    msg: json!(15)
+                },
+            ]
+        );
+        assert_eq!(
+            msgs["k2"],
+            vec![LogRecord {
+                offset: 2000,
+                msg: json!(7)
+            }]
+        );
+    } else {
+        panic!("expected poll_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_kafka_commit_offsets_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"commit_offsets","offsets":{"k1":1000},"msg_id":3},"src":"c2","id":4}"#;
+    let msg: Msg<Kafka, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body: Workload(Kafka::CommitOffsets { msg_id, offsets }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*msg_id, 3);
+        assert_eq!(offsets["k1"], 1000);
+    } else {
+        panic!("expected commit_offsets message")
+    }
+    assert_serde_preserves_identity(&msg);
+    let buf = r#"{"dest":"c2","body":{"type":"commit_offsets_ok","in_reply_to":3},"src":"n1"}"#;
+    let msg: Msg<Kafka, ()> = serde_json::from_str(buf).expect("message");
+    assert!(matches!(
+        msg.body,
+        Workload(Kafka::CommitOffsetsOk { in_reply_to: 3, .. })
+    ));
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_kafka_list_committed_offsets_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"list_committed_offsets","keys":["k1","k2"],"msg_id":4},"src":"c2","id":5}"#;
+    let msg: Msg<Kafka, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body: Workload(Kafka::ListCommittedOffsets { msg_id, keys }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*msg_id, 4);
+        assert_eq!(keys, &vec!["k1".to_string(), "k2".to_string()]);
+    } else {
+        panic!("expected list_committed_offsets message")
+    }
+    assert_serde_preserves_identity(&msg);
+    let buf = r#"{"dest":"c2","body":{"type":"list_committed_offsets_ok","offsets":{"k1":1000,"k2":2000},"in_reply_to":4},"src":"n1"}"#;
+    let msg: Msg<Kafka, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body:
+            Workload(Kafka::ListCommittedOffsetsOk {
+                in_reply_to,
+                offsets,
+                ..
+            }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*in_reply_to, 4);
+        assert_eq!(offsets["k1"], 1000);
+        assert_eq!(offsets["k2"], 2000);
+    } else {
+        panic!("expected list_committed_offsets_ok message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_linkv_read_msg() {
+    let buf = r#"{"dest":"n4","body":{"key":0,"type":"read","msg_id":1},"src":"c10","id":10}"#;
+    let msg: Msg<LinKv, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body: Workload(LinKv::Read { msg_id, key }),
+    } = &msg
+    {
+        assert_eq!(dest, "n4");
+        assert_eq!(src, "c10");
+        assert_eq!(key, &json!(0));
+        assert_eq!(*msg_id, 1);
+    } else {
+        panic!("expected read message");
+    }
+
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_linkv_read_ok_msg() {
+    let buf = r#"{"dest":"n4","body":{"type": "read_ok", "value": 1, "msg_id": 0 , "in_reply_to": 2},"src":"c10","id":10}"#;
+    let msg: Msg<LinKv, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        src,
+        dest,
+        body:
+            Workload(LinKv::ReadOk {
+                in_reply_to,
+                msg_id,
+                value,
+            }),
+    } = &msg
+    {
+        assert_eq!(dest, "n4");
+        assert_eq!(src, "c10");
+        assert_eq!(value, &json!(1));
+        assert_eq!(msg_id, &Some(0));
+        assert_eq!(*in_reply_to, 2);
+    } else {
+        panic!("expected read message");
+    }
+
+    assert_serde_preserves_identity(&msg);
+}
 
 #[test]
-fn serde_cas_msg() {
-    let buf = r#"{"dest":"n1","body":{"key":0,"from":4,"to":2,"type":"cas","msg_id":1},"src":"c11","id":11}"#;
-    let msg: Msg<LinKv, ()> = serde_json::from_str(&buf).expect("message");
+fn serde_error_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"error","in_reply_to":5,"code":11,"text":"try again"},"src":"n2"}"#;
+    let msg: Msg<Echo, ()> = serde_json::from_str(buf).expect("message");
     if let Msg {
-        src,
-        dest,
         body:
-            Workload(LinKv::Cas {
-                msg_id,
-                key,
-                from,
-                to,
+            Body::Error(Error {
+                in_reply_to,
+                code,
+                text,
             }),
+        ..
     } = &msg
     {
-        assert_eq!(dest, "n1");
-        assert_eq!(src, "c11");
-        assert_eq!(key, &json!(0));
-        assert_eq!(from, &json!(4));
-        assert_eq!(to, &json!(2));
-        assert_eq!(*msg_id, 1);
+        assert_eq!(*in_reply_to, 5);
+        assert_eq!(*code, ErrorCode::TemporarilyUnavailable);
+        assert!(code.is_definite());
+        assert_eq!(text, "try again");
     } else {
-        panic!("expected cas message")
+        panic!("expected error message")
     }
     assert_serde_preserves_identity(&msg);
 }
 
 #[test]
-fn serde_cas_ok_msg() {
-    let buf = r#"{"dest":"n1","body":{ "type": "cas_ok", "in_reply_to": 1 },"src":"c11","id":11}"#;
-    let msg: Msg<LinKv, ()> = serde_json::from_str(&buf).expect("message");
-    if let Msg {
-        src,
-        dest,
-        body: Workload(LinKv::CasOk {
-            in_reply_to,
-            msg_id,
-        }),
-    } = &msg
+fn serde_envelope_id() {
+    let buf = r#"{"dest":"n1","body":{"echo":"Please echo 36","type":"echo","msg_id":1},"src":"c10","id":10}"#;
+    let msg: Extensible<Msg<Echo, ()>> = serde_json::from_str(buf).expect("message");
+    assert_eq!(msg.id(), Some(10));
+    let value = serde_json::to_value(&msg).expect("JSON value");
+    assert_eq!(value["id"], json!(10));
+
+    let buf =
+        r#"{"dest":"n1","body":{"echo":"Please echo 36","type":"echo","msg_id":1},"src":"c10"}"#;
+    let msg: Extensible<Msg<Echo, ()>> = serde_json::from_str(buf).expect("message");
+    assert_eq!(msg.id(), None);
+    let data = serde_json::to_string(&msg).expect("JSON data");
+    assert!(!data.contains("\"id\""), "unexpected id in {}", data);
+}
+
+#[test]
+fn serde_extensible_preserves_unknown_fields() {
+    let buf = r#"{"dest":"n1","body":{"echo":"hi","type":"echo","msg_id":1,"trace":"abc","hops":[1,2]},"src":"c10","id":10,"via":"proxy"}"#;
+    let msg: Extensible<Msg<Extensible<Echo>, ()>> = serde_json::from_str(buf).expect("message");
+    assert_eq!(msg.extra.len(), 2);
+    assert_eq!(msg.extra["via"], json!("proxy"));
+    assert_eq!(msg.id(), Some(10));
+    if let Workload(Extensible {
+        inner: Echo::Echo { msg_id, echo },
+        extra,
+    }) = &msg.inner.body
     {
-        assert_eq!(dest, "n1");
-        assert_eq!(src, "c11");
-        assert_eq!(*in_reply_to, 1);
-        assert_eq!(msg_id, &None);
+        assert_eq!(*msg_id, 1);
+        assert_eq!(echo, &json!("hi"));
+        assert_eq!(extra.len(), 2);
+        assert_eq!(extra["trace"], json!("abc"));
+        assert_eq!(extra["hops"], json!([1, 2]));
     } else {
-        panic!("expected cas_ok message")
+        panic!("expected echo message")
     }
+    assert_eq!(
+        serde_json::to_value(&msg).expect("JSON value"),
+        serde_json::from_str::<Value>(buf).expect("JSON value")
+    );
     assert_serde_preserves_identity(&msg);
 }
 
 #[test]
-fn serde_echo_msg() {
-    let buf = r#"{"dest":"n1","body":{"echo":"Please echo 36","type":"echo","msg_id":1},"src":"c10","id":10}"#;
-    let msg: Msg<Echo, ()> = serde_json::from_str(&buf).expect("echo message");
+fn serde_extensible_without_unknown_fields() {
+    let buf = r#"{"dest":"n1","body":{"type":"read","msg_id":1},"src":"c10"}"#;
+    let msg: Msg<Extensible<PnCounter>, ()> = serde_json::from_str(buf).expect("message");
+    assert_eq!(
+        msg.body,
+        Workload(Extensible::new(PnCounter::Read { msg_id: 1 }))
+    );
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_gcounter_add_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"add","delta":3,"msg_id":1},"src":"c10","id":10}"#;
+    let msg: Msg<GCounter, ()> = serde_json::from_str(buf).expect("message");
     if let Msg {
         src,
         dest,
-        body: Workload(Echo::Echo { msg_id, echo }),
+        body: Workload(GCounter::Add { msg_id, delta }),
     } = &msg
     {
         assert_eq!(dest, "n1");
         assert_eq!(src, "c10");
-        assert_eq!(echo, "Please echo 36");
+        assert_eq!(*delta, 3);
         assert_eq!(*msg_id, 1);
     } else {
-        panic!("expected echo message")
+        panic!("expected add message");
     }
+
     assert_serde_preserves_identity(&msg);
 }
 
 #[test]
-fn serde_init_msg() {
-    let buf = r#"{"dest":"n1","body":{"type":"init","node_id":"n1","node_ids":["n1","n2","n3","n4","n5"],"msg_id":1},"src":"c4","id":4}"#;
-    let msg: Msg<Echo, ()> = serde_json::from_str(&buf).expect("message");
+fn serde_gcounter_add_negative_delta() {
+    let buf = r#"{"dest":"n1","body":{"type":"add","delta":-3,"msg_id":1},"src":"c10","id":10}"#;
+    assert!(serde_json::from_str::<Msg<GCounter, ()>>(buf).is_err());
+}
+
+#[test]
+fn serde_gcounter_read_ok_msg() {
+    let buf = r#"{"dest":"c10","body":{"type":"read_ok","value":42,"in_reply_to":2},"src":"n1"}"#;
+    let msg: Msg<GCounter, ()> = serde_json::from_str(buf).expect("message");
     if let Msg {
-        src,
-        dest,
         body:
-            Body::Init(Init::Init {
+            Workload(GCounter::ReadOk {
+                in_reply_to,
                 msg_id,
-                node_id,
-                node_ids,
+                value,
             }),
+        ..
     } = &msg
     {
-        assert_eq!(dest, "n1");
-        assert_eq!(src, "c4");
-        assert_eq!(node_id, "n1");
-        assert_eq!(
-            node_ids,
-            &vec!["n1", "n2", "n3", "n4", "n5"]
-                .iter()
-                .map(|s| s.to_string())
-                .collect::<Vec<_>>()
-        );
+        assert_eq!(*value, 42);
+        assert_eq!(msg_id, &None);
+        assert_eq!(*in_reply_to, 2);
+    } else {
+        panic!("expected read_ok message");
+    }
+
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_pncounter_add_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"add","delta":-7,"msg_id":1},"src":"c10","id":10}"#;
+    let msg: Msg<PnCounter, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body: Workload(PnCounter::Add { msg_id, delta }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*delta, -7);
         assert_eq!(*msg_id, 1);
     } else {
-        panic!("expected init message")
+        panic!("expected add message");
     }
+
     assert_serde_preserves_identity(&msg);
 }
 
 #[test]
-fn serde_init_ok_msg() {
-    let buf = r#"{"src":"n1","dest":"c4","body":{"type":"init_ok","in_reply_to":1,"msg_id":0}}"#;
-    let msg: Msg<Init, ()> = serde_json::from_str(&buf).expect("message");
+fn serde_pncounter_add_ok_msg() {
+    let buf = r#"{"dest":"c10","body":{"type":"add_ok","in_reply_to":1,"msg_id":5},"src":"n1"}"#;
+    let msg: Msg<PnCounter, ()> = serde_json::from_str(buf).expect("message");
     if let Msg {
         src,
         dest,
-        body: Body::Init(Init::InitOk {
+        body: Workload(PnCounter::AddOk {
             in_reply_to,
             msg_id,
         }),
     } = &msg
     {
-        assert_eq!(dest, "c4");
+        assert_eq!(dest, "c10");
         assert_eq!(src, "n1");
         assert_eq!(*in_reply_to, 1);
-        assert_eq!(*msg_id, 0);
+        assert_eq!(msg_id, &Some(5));
     } else {
-        panic!("expected init_ok message, got {:?}", msg);
+        panic!("expected add_ok message");
     }
+
     assert_serde_preserves_identity(&msg);
 }
 
 #[test]
-fn serde_linkv_read_msg() {
-    let buf = r#"{"dest":"n4","body":{"key":0,"type":"read","msg_id":1},"src":"c10","id":10}"#;
-    let msg: Msg<LinKv, ()> = serde_json::from_str(&buf).expect("message");
+fn serde_pn_read_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"read","msg_id":1},"src":"c10","id":10}"#;
+    let msg: Msg<PnCounter, ()> = serde_json::from_str(buf).expect("message");
     if let Msg {
         src,
         dest,
-        body: Workload(LinKv::Read { msg_id, key }),
+        body: Workload(PnCounter::Read { msg_id }),
     } = &msg
     {
-        assert_eq!(dest, "n4");
+        assert_eq!(dest, "n1");
         assert_eq!(src, "c10");
-        assert_eq!(key, &json!(0));
         assert_eq!(*msg_id, 1);
     } else {
         panic!("expected read message");
@@ -326,25 +1827,25 @@ fn serde_linkv_read_msg() {
 }
 
 #[test]
-fn serde_linkv_read_ok_msg() {
-    let buf = r#"{"dest":"n4","body":{"type": "read_ok", "value": 1, "msg_id": 0 , "in_reply_to": 2},"src":"c10","id":10}"#;
-    let msg: Msg<LinKv, ()> = serde_json::from_str(&buf).expect("message");
+fn serde_pncounter_read_ok_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"read_ok","value":1,"msg_id": 2,"in_reply_to":0},"src":"c10","id":10}"#;
+    let msg: Msg<PnCounter, ()> = serde_json::from_str(buf).expect("message");
     if let Msg {
         src,
         dest,
         body:
-            Workload(LinKv::ReadOk {
+            Workload(PnCounter::ReadOk {
                 in_reply_to,
                 msg_id,
                 value,
             }),
     } = &msg
     {
-        assert_eq!(dest, "n4");
+        assert_eq!(dest, "n1");
         assert_eq!(src, "c10");
         assert_eq!(value, &json!(1));
-        assert_eq!(msg_id, &Some(0));
-        assert_eq!(*in_reply_to, 2);
+        assert_eq!(msg_id, &Some(2));
+        assert_eq!(*in_reply_to, 0);
     } else {
         panic!("expected read message");
     }
@@ -353,52 +1854,223 @@ fn serde_linkv_read_ok_msg() {
 }
 
 #[test]
-fn serde_pn_read_msg() {
-    let buf = r#"{"dest":"n1","body":{"type":"read","msg_id":1},"src":"c10","id":10}"#;
-    let msg: Msg<PnCounter, ()> = serde_json::from_str(&buf).expect("message");
+fn serde_txn_list_append_txn_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"txn","msg_id":3,"txn":[["r",1,null],["append",1,6],["append",2,9]]},"src":"c5","id":5}"#;
+    let msg: Msg<TxnListAppend, ()> = serde_json::from_str(buf).expect("message");
     if let Msg {
         src,
         dest,
-        body: Workload(PnCounter::Read { msg_id }),
+        body: Workload(TxnListAppend::Txn { msg_id, txn }),
     } = &msg
     {
         assert_eq!(dest, "n1");
-        assert_eq!(src, "c10");
-        assert_eq!(*msg_id, 1);
+        assert_eq!(src, "c5");
+        assert_eq!(*msg_id, 3);
+        assert_eq!(
+            txn,
+            &vec![
+                ListAppendOp::Read {
+                    key: json!(1),
+                    value: None
+                },
+                ListAppendOp::Append {
+                    key: json!(1),
+                    element: json!(6)
+                },
+                ListAppendOp::Append {
+                    key: json!(2),
+                    element: json!(9)
+                },
+            ]
+        );
     } else {
-        panic!("expected read message");
+        panic!("expected txn message")
     }
+    assert_serde_preserves_identity(&msg);
+}
 
+#[test]
+fn serde_txn_list_append_txn_ok_msg() {
+    let buf = r#"{"dest":"c5","body":{"type":"txn_ok","in_reply_to":3,"txn":[["r",1,[1,2,6]],["append",1,7]]},"src":"n1"}"#;
+    let msg: Msg<TxnListAppend, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
+        body:
+            Workload(TxnListAppend::TxnOk {
+                in_reply_to,
+                msg_id,
+                txn,
+            }),
+        ..
+    } = &msg
+    {
+        assert_eq!(*in_reply_to, 3);
+        assert_eq!(msg_id, &None);
+        assert_eq!(
+            txn,
+            &vec![
+                ListAppendOp::Read {
+                    key: json!(1),
+                    value: Some(vec![json!(1), json!(2), json!(6)])
+                },
+                ListAppendOp::Append {
+                    key: json!(1),
+                    element: json!(7)
+                },
+            ]
+        );
+    } else {
+        panic!("expected txn_ok message")
+    }
     assert_serde_preserves_identity(&msg);
 }
 
 #[test]
-fn serde_pncounter_read_ok_msg() {
-    let buf = r#"{"dest":"n1","body":{"type":"read_ok","value":1,"msg_id": 2,"in_reply_to":0},"src":"c10","id":10}"#;
-    let msg: Msg<PnCounter, ()> = serde_json::from_str(&buf).expect("message");
+fn serde_list_append_op_encoding() {
+    let read = ListAppendOp::Read {
+        key: json!(4),
+        value: None,
+    };
+    assert_eq!(
+        serde_json::to_value(&read).expect("JSON"),
+        json!(["r", 4, null])
+    );
+    let append = ListAppendOp::Append {
+        key: json!(4),
+        element: json!(2),
+    };
+    assert_eq!(
+        serde_json::to_value(&append).expect("JSON"),
+        json!(["append", 4, 2])
+    );
+    assert!(serde_json::from_value::<ListAppendOp>(json!(["w", 4, 2])).is_err());
+    assert!(serde_json::from_value::<ListAppendOp>(json!(["r", 4, 2])).is_err());
+    assert!(serde_json::from_value::<ListAppendOp>(json!(["append", 4])).is_err());
+}
+
+#[test]
+fn serde_txn_rw_register_txn_msg() {
+    let buf = r#"{"dest":"n1","body":{"type":"txn","msg_id":3,"txn":[["r",1,null],["w",1,6],["w",2,9]]},"src":"c5","id":5}"#;
+    let msg: Msg<TxnRwRegister, ()> = serde_json::from_str(buf).expect("message");
     if let Msg {
         src,
         dest,
+        body: Workload(TxnRwRegister::Txn { msg_id, txn }),
+    } = &msg
+    {
+        assert_eq!(dest, "n1");
+        assert_eq!(src, "c5");
+        assert_eq!(*msg_id, 3);
+        assert_eq!(
+            txn,
+            &vec![
+                RwRegisterOp::Read {
+                    key: json!(1),
+                    value: None
+                },
+                RwRegisterOp::Write {
+                    key: json!(1),
+                    value: json!(6)
+                },
+                RwRegisterOp::Write {
+                    key: json!(2),
+                    value: json!(9)
+                },
+            ]
+        );
+    } else {
+        panic!("expected txn message")
+    }
+    assert_serde_preserves_identity(&msg);
+}
+
+#[test]
+fn serde_txn_rw_register_txn_ok_msg() {
+    let buf = r#"{"dest":"c5","body":{"type":"txn_ok","in_reply_to":3,"msg_id":8,"txn":[["r",1,4],["r",3,null],["w",1,6]]},"src":"n1"}"#;
+    let msg: Msg<TxnRwRegister, ()> = serde_json::from_str(buf).expect("message");
+    if let Msg {
         body:
-            Workload(PnCounter::ReadOk {
+            Workload(TxnRwRegister::TxnOk {
                 in_reply_to,
                 msg_id,
-                value,
+                txn,
             }),
+        ..
     } = &msg
     {
-        assert_eq!(dest, "n1");
-        assert_eq!(src, "c10");
-        assert_eq!(value, &json!(1));
-        assert_eq!(msg_id, &Some(2));
-        assert_eq!(*in_reply_to, 0);
+        assert_eq!(*in_reply_to, 3);
+        assert_eq!(msg_id, &Some(8));
+        assert_eq!(
+            txn,
+            &vec![
+                RwRegisterOp::Read {
+                    key: json!(1),
+                    value: Some(json!(4))
+                },
+                RwRegisterOp::Read {
+                    key: json!(3),
+                    value: None
+                },
+                RwRegisterOp::Write {
+                    key: json!(1),
+                    value: json!(6)
+                },
+            ]
+        );
     } else {
-        panic!("expected read message");
+        panic!("expected txn_ok message")
     }
-
     assert_serde_preserves_identity(&msg);
 }
 
+#[test]
+fn serde_rw_register_op_encoding() {
+    let read = RwRegisterOp::Read {
+        key: json!(4),
+        value: None,
+    };
+    assert_eq!(
+        serde_json::to_value(&read).expect("JSON"),
+        json!(["r", 4, null])
+    );
+    let write = RwRegisterOp::Write {
+        key: json!(4),
+        value: json!(2),
+    };
+    assert_eq!(
+        serde_json::to_value(&write).expect("JSON"),
+        json!(["w", 4, 2])
+    );
+    assert!(serde_json::from_value::<RwRegisterOp>(json!(["append", 4, 2])).is_err());
+    assert!(serde_json::from_value::<RwRegisterOp>(json!(["w", 4])).is_err());
+}
+
+#[test]
+fn msg_id_generator() {
+    let g = MsgIdGenerator::new(3);
+    assert_eq!(g.peek(), 3);
+    assert_eq!(g.next_msg_id(), 3);
+    assert_eq!(g.next_msg_id(), 4);
+    assert_eq!(g.peek(), 5);
+    assert_eq!(MsgIdGenerator::default().next_msg_id(), 0);
+}
+
+#[test]
+fn body_types_of_bodies() {
+    use BodyTypes::{Empty, Tagged, Unknown};
+    assert_eq!(body_types::<Echo>(), Tagged(&["echo", "echo_ok"]));
+    assert_eq!(body_types::<Init>(), Tagged(&["init", "init_ok"]));
+    assert_eq!(
+        body_types::<Extensible<Echo>>(),
+        Tagged(&["echo", "echo_ok"])
+    );
+    assert_eq!(body_types::<Error>(), Unknown);
+    assert_eq!(body_types::<()>(), Empty);
+    assert_eq!(body_types::<Typed>(), Tagged(&["bar", "baz"]));
+    assert_eq!(body_types::<Extensible<Typed>>(), Tagged(&["bar", "baz"]));
+    assert_eq!(body_types::<Untyped>(), Unknown);
+    assert_eq!(body_types::<Body<Echo, ()>>(), Unknown);
+}
+
 #[test]
 fn serde_typed_bar() {
     let bar = Typed::Bar {
@@ -412,7 +2084,7 @@ fn serde_typed_bar() {
     };
     let data = serde_json::to_string(m).expect("JSON data");
     println!("{}", data);
-    let de_m: Msg<Echo, Typed> = serde_json::from_str(&data).expect(&format!("{:?}", m));
+    let de_m: Msg<Echo, Typed> = serde_json::from_str(&data).unwrap_or_else(|_| panic!("{:?}", m));
     assert_eq!(
         m, &de_m,
         "expected deserialized NetMsg={:?} from data={}, but got NetMsg={:?}",
@@ -442,7 +2114,7 @@ fn serde_typed_baz() {
     };
     let data = serde_json::to_string(m).expect("JSON data");
     println!("{}", data);
-    let de_m: Msg<Echo, Typed> = serde_json::from_str(&data).expect(&format!("{:?}", m));
+    let de_m: Msg<Echo, Typed> = serde_json::from_str(&data).unwrap_or_else(|_| panic!("{:?}", m));
     assert_eq!(
         m, &de_m,
         "expected deserialized NetMsg={:?} from data={}, but got NetMsg={:?}",
@@ -472,7 +2144,8 @@ fn serde_untyped_bar() {
     };
     let data = serde_json::to_string(m).expect("JSON data");
     println!("{}", data);
-    let de_m: Msg<Echo, Untyped> = serde_json::from_str(&data).expect(&format!("{:?}", m));
+    let de_m: Msg<Echo, Untyped> =
+        serde_json::from_str(&data).unwrap_or_else(|_| panic!("{:?}", m));
     assert_eq!(
         m, &de_m,
         "expected deserialized NetMsg={:?} from data={}, but got NetMsg={:?}",
@@ -502,7 +2175,8 @@ fn serde_untyped_baz() {
     };
     let data = serde_json::to_string(m).expect("JSON data");
     println!("{}", data);
-    let de_m: Msg<Echo, Untyped> = serde_json::from_str(&data).expect(&format!("{:?}", m));
+    let de_m: Msg<Echo, Untyped> =
+        serde_json::from_str(&data).unwrap_or_else(|_| panic!("{:?}", m));
     assert_eq!(
         m, &de_m,
         "expected deserialized NetMsg={:?} from data={}, but got NetMsg={:?}",
@@ -533,9 +2207,10 @@ fn serde_untyped_into_typed() {
     };
     let data = serde_json::to_string(m).expect("JSON data");
     println!("{}", data);
-    if let Ok(_) = serde_json::from_str::<Untyped>(&data) {
-        assert!(false, "invalid deserialization")
-    }
+    assert!(
+        serde_json::from_str::<Untyped>(&data).is_err(),
+        "invalid deserialization"
+    );
 }
 
 /// Typed body has a `type` tag to indicate deserialization target type
@@ -549,6 +2224,215 @@ enum Typed {
     Baz { id: u64, value: String },
 }
 
+#[test]
+fn reply_msg() {
+    let request: Msg<Echo, ()> = Msg {
+        src: "c1".to_string(),
+        dest: "n1".to_string(),
+        body: Workload(Echo::Echo {
+            msg_id: 3,
+            echo: json!("boo!"),
+        }),
+    };
+    assert_eq!(request.msg_id(), Some(3));
+    let reply = request
+        .reply(|in_reply_to| {
+            Workload(Echo::EchoOk {
+                in_reply_to,
+                msg_id: None,
+                echo: json!("boo!"),
+            })
+        })
+        .expect("reply");
+    assert_eq!(
+        reply,
+        Msg {
+            src: "n1".to_string(),
+            dest: "c1".to_string(),
+            body: Workload(Echo::EchoOk {
+                in_reply_to: 3,
+                msg_id: None,
+                echo: json!("boo!"),
+            }),
+        }
+    );
+}
+
+#[test]
+fn reply_error_msg() {
+    let request: Msg<LinKv, ()> = Msg {
+        src: "c1".to_string(),
+        dest: "n1".to_string(),
+        body: Workload(LinKv::Read {
+            msg_id: 5,
+            key: json!(1),
+        }),
+    };
+    let reply = request
+        .reply_error(ErrorCode::KeyDoesNotExist, "no key 1")
+        .expect("reply");
+    assert_eq!(reply.src, "n1");
+    assert_eq!(reply.dest, "c1");
+    assert_eq!(
+        reply.body,
+        Body::Error(Error {
+            in_reply_to: 5,
+            code: ErrorCode::KeyDoesNotExist,
+            text: "no key 1".to_string(),
+        })
+    );
+}
+
+#[test]
+fn reply_without_msg_id() {
+    let reply: Msg<Echo, ()> = Msg {
+        src: "n1".to_string(),
+        dest: "c1".to_string(),
+        body: Workload(Echo::EchoOk {
+            in_reply_to: 3,
+            msg_id: None,
+            echo: json!("boo!"),
+        }),
+    };
+    assert_eq!(reply.msg_id(), None);
+    assert!(matches!(
+        reply.reply_error(ErrorCode::Abort, "no"),
+        Err(crate::Error::MissingMsgId)
+    ));
+}
+
+#[cfg(test)]
+fn assert_msg_body_matches_serialized<B: MsgBody + Serialize + Debug>(body: &B) {
+    assert_eq!(
+        body.msg_id(),
+        serialized_field(body, "msg_id").and_then(|v| v.as_u64()),
+        "msg_id of {:?}",
+        body
+    );
+    assert_eq!(
+        body.in_reply_to(),
+        serialized_field(body, "in_reply_to").and_then(|v| v.as_u64()),
+        "in_reply_to of {:?}",
+        body
+    );
+    assert_eq!(
+        body.type_name().as_deref(),
+        serialized_field(body, "type")
+            .as_ref()
+            .and_then(Value::as_str),
+        "type of {:?}",
+        body
+    );
+}
+
+#[test]
+fn msg_body_fields() {
+    let bodies: Vec<Body<Kafka, ()>> = vec![
+        Workload(Kafka::Send {
+            msg_id: 1,
+            key: "k".to_string(),
+            msg: json!(1),
+        }),
+        Workload(Kafka::ListCommittedOffsetsOk {
+            in_reply_to: 2,
+            msg_id: Some(3),
+            offsets: HashMap::new(),
+        }),
+        Workload(Kafka::CommitOffsetsOk {
+            in_reply_to: 4,
+            msg_id: None,
+        }),
+        Body::Error(Error {
+            in_reply_to: 5,
+            code: ErrorCode::Abort,
+            text: "aborted".to_string(),
+        }),
+        Body::Init(Init::InitOk {
+            in_reply_to: 6,
+            msg_id: 7,
+        }),
+        Body::Application(()),
+    ];
+    for body in &bodies {
+        assert_msg_body_matches_serialized(body);
+    }
+    assert_msg_body_matches_serialized(&Broadcast::Topology {
+        msg_id: 8,
+        topology: HashMap::new(),
+    });
+    assert_msg_body_matches_serialized(&LinKv::WriteOk {
+        in_reply_to: 9,
+        msg_id: None,
+    });
+    assert_msg_body_matches_serialized(&Extensible::new(UniqueIds::Generate { msg_id: 10 }));
+    assert_eq!(
+        bodies[1].type_name().as_deref(),
+        Some("list_committed_offsets_ok")
+    );
+}
+
+/// Assert the [MsgBody] fields of the workload `T` body in `data` match its serialized fields
+#[cfg(test)]
+fn assert_parsed_msg_body_matches_serialized<T>(data: &str)
+where
+    T: MsgBody + Serialize + DeserializeOwned + Debug,
+{
+    let body: T = serde_json::from_str(data).unwrap_or_else(|e| panic!("{}: {}", data, e));
+    assert_msg_body_matches_serialized(&body);
+}
+
+#[test]
+fn msg_body_fields_of_all_workloads() {
+    let request = |t: &str| format!(r#"{{"type":"{}","msg_id":1}}"#, t);
+    let response = |t: &str| format!(r#"{{"type":"{}","in_reply_to":1,"msg_id":2}}"#, t);
+    assert_parsed_msg_body_matches_serialized::<GSet>(&request("read"));
+    assert_parsed_msg_body_matches_serialized::<GCounter>(&request("read"));
+    assert_parsed_msg_body_matches_serialized::<PnCounter>(&request("read"));
+    assert_parsed_msg_body_matches_serialized::<Broadcast>(&request("read"));
+    assert_parsed_msg_body_matches_serialized::<GSet>(&response("add_ok"));
+    assert_parsed_msg_body_matches_serialized::<GCounter>(&response("add_ok"));
+    assert_parsed_msg_body_matches_serialized::<PnCounter>(&response("add_ok"));
+    for t in ["broadcast_ok", "topology_ok"] {
+        assert_parsed_msg_body_matches_serialized::<Broadcast>(&response(t));
+    }
+    for t in ["cas_ok", "write_ok"] {
+        assert_parsed_msg_body_matches_serialized::<LinKv>(&response(t));
+    }
+    assert_parsed_msg_body_matches_serialized::<Kafka>(&response("commit_offsets_ok"));
+    assert_parsed_msg_body_matches_serialized::<UniqueIds>(&request("generate"));
+    assert_parsed_msg_body_matches_serialized::<Init>(
+        r#"{"type":"init","msg_id":1,"node_id":"n1","node_ids":["n1"]}"#,
+    );
+    assert_parsed_msg_body_matches_serialized::<TxnRwRegister>(
+        r#"{"type":"txn","msg_id":1,"txn":[["r",1,null]]}"#,
+    );
+    assert_parsed_msg_body_matches_serialized::<TxnListAppend>(
+        r#"{"type":"txn_ok","in_reply_to":1,"txn":[["append",1,2]]}"#,
+    );
+    assert_parsed_msg_body_matches_serialized::<Echo>(
+        r#"{"type":"echo_ok","in_reply_to":1,"echo":"boo!"}"#,
+    );
+}
+
+#[test]
+fn msg_body_application() {
+    #[derive(Debug, Serialize)]
+    #[serde(tag = "type", rename = "gossip")]
+    struct Gossip {
+        msg_id: MsgId,
+        peers: Vec<Id>,
+    }
+    impl MsgBody for Gossip {}
+
+    let body: Body<Echo, Gossip> = Body::Application(Gossip {
+        msg_id: 3,
+        peers: vec!["n2".to_string()],
+    });
+    assert_eq!(body.msg_id(), Some(3));
+    assert_eq!(body.in_reply_to(), None);
+    assert_eq!(body.type_name().as_deref(), Some("gossip"));
+}
+
 /// Untyped body has no `type` tag to indicate deserialization target type
 ///
 /// Untyped bodies are deserialized into a specifiedstruct or the first enumerated type that fits.
@@ -570,7 +2454,7 @@ where
 {
     let data = serde_json::to_string(m).expect("JSON data");
     println!("{}", data);
-    let de_m: M = serde_json::from_str(&data).expect(&format!("{:?}", m));
+    let de_m: M = serde_json::from_str(&data).unwrap_or_else(|_| panic!("{:?}", m));
     assert_eq!(
         m, &de_m,
         "expected deserialized M={:?} from data={}, but got M={:?}",
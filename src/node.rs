@@ -0,0 +1,578 @@
+//! Handler style node processes
+//!
+//! A [Node] is called to handle each message it receives, rather than running its own receive
+//! loop like a [Process]. The node interacts with the network through a [Context]. Wrap a node in
+//! a [NodeProcess] to run it with a [Runtime](crate::runtime::Runtime).
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(test)]
+use async_channel::bounded;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+#[cfg(test)]
+use serde_json::Value;
+#[cfg(test)]
+use tokio::test;
+
+#[cfg(test)]
+use crate::msg::Body::Workload;
+#[cfg(test)]
+use crate::msg::Echo;
+use crate::msg::{Body, Msg, MsgBody, MsgId};
+use crate::process::{Concurrency, Event, ProcNet, Process};
+use crate::rpc::{Embed, Request};
+#[cfg(test)]
+use crate::runtime::new_uninitialized_for_test;
+use crate::timer::{Timer, TimerId};
+use crate::{ErrorCode, Id, Result, Status};
+
+/// A node's interface to the Maelstrom network, passed to each [Node] handler call
+///
+/// Parameters
+/// - `W` the workload body type, e.g. [Echo](crate::msg::Echo)
+/// - `A` the application body type
+pub struct Context<W, A>
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    args: Vec<String>,
+    net: ProcNet<W, A>,
+    id: Id,
+    ids: Vec<Id>,
+}
+
+impl<W, A> Context<W, A>
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    /// Get the pass through command line args
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Get this node's ID
+    pub fn id(&self) -> &Id {
+        &self.id
+    }
+
+    /// Get all protocol participants' IDs
+    pub fn ids(&self) -> &[Id] {
+        &self.ids
+    }
+
+    /// Allocate a unique message ID
+    ///
+    /// See [ProcNet::next_msg_id].
+    pub fn next_msg_id(&self) -> MsgId {
+        self.net.next_msg_id()
+    }
+
+    /// Send a message with `body` to `dest`
+    pub async fn send(&self, dest: Id, body: Body<W, A>) -> Status {
+        self.net
+            .txq
+            .send(Msg {
+                src: self.id.clone(),
+                dest,
+                body,
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Send a reply to `request`
+    ///
+    /// See [Msg::reply].
+    pub async fn reply(&self, request: &Msg<W, A>, body: impl FnOnce(MsgId) -> Body<W, A>) -> Status
+    where
+        W: MsgBody,
+        A: MsgBody,
+    {
+        self.net.txq.send(request.reply(body)?).await?;
+        Ok(())
+    }
+
+    /// Send an [Error](crate::msg::Error) reply to `request`
+    ///
+    /// See [Msg::reply_error].
+    pub async fn reply_error(
+        &self,
+        request: &Msg<W, A>,
+        code: ErrorCode,
+        text: impl Into<String>,
+    ) -> Status
+    where
+        W: MsgBody,
+        A: MsgBody,
+    {
+        self.net.txq.send(request.reply_error(code, text)?).await?;
+        Ok(())
+    }
+
+    /// Send a request and wait for its reply
+    ///
+    /// See [ProcNet::call].
+    pub async fn call(&self, dest: Id, body: Body<W, A>) -> Result<Msg<W, A>>
+    where
+        W: MsgBody,
+        A: MsgBody,
+    {
+        self.net.call(dest, body).await
+    }
+
+    /// Send a request and wait up to `timeout` for its reply
+    ///
+    /// See [ProcNet::call_with_timeout].
+    pub async fn call_with_timeout(
+        &self,
+        dest: Id,
+        body: Body<W, A>,
+        timeout: Duration,
+    ) -> Result<Msg<W, A>>
+    where
+        W: MsgBody,
+        A: MsgBody,
+    {
+        self.net.call_with_timeout(dest, body, timeout).await
+    }
+
+    /// Send a typed request and wait for its response
+    ///
+    /// See [ProcNet::request].
+    pub async fn request<R, V>(
+        &self,
+        dest: Id,
+        request: R,
+    ) -> std::result::Result<R::Response, R::Error>
+    where
+        W: MsgBody,
+        A: MsgBody,
+        R: Request,
+        Body<W, A>: Embed<R::Body, V>,
+    {
+        self.net.request(dest, request).await
+    }
+
+    /// Send a typed request and wait up to `timeout` for its response
+    ///
+    /// See [ProcNet::request_with_timeout].
+    pub async fn request_with_timeout<R, V>(
+        &self,
+        dest: Id,
+        request: R,
+        timeout: Duration,
+    ) -> std::result::Result<R::Response, R::Error>
+    where
+        W: MsgBody,
+        A: MsgBody,
+        R: Request,
+        Body<W, A>: Embed<R::Body, V>,
+    {
+        self.net.request_with_timeout(dest, request, timeout).await
+    }
+
+    /// Schedule [Node::timer] to be called once, after `delay`
+    pub fn schedule_once(&self, delay: Duration) -> Timer {
+        self.net.schedule_once(delay)
+    }
+
+    /// Schedule [Node::timer] to be called every `period`
+    pub fn schedule_periodic(&self, period: Duration) -> Timer {
+        self.net.schedule_periodic(period)
+    }
+}
+
+/// Handler style Maelstrom node
+///
+/// Parameters
+/// - `W` the workload body type, e.g. [Echo](crate::msg::Echo)
+/// - `A` the application body type
+#[async_trait]
+pub trait Node<W, A>: Send + Sync
+where
+    W: DeserializeOwned + Serialize + Send,
+    A: DeserializeOwned + Serialize + Send,
+{
+    /// Initialize the node, before any message is handled
+    ///
+    /// IO runs meanwhile, so the node may call services, e.g. via [Context::request]. Messages
+    /// received meanwhile are handled once initialized.
+    ///
+    /// Return
+    /// - [Ok] IFF initialized, which the default always is,
+    /// - [Err] otherwise, which fails the node's initialization
+    async fn init(&self, _ctx: &Context<W, A>) -> Status {
+        Ok(())
+    }
+
+    /// Handle a received message
+    ///
+    /// Return
+    /// - [Ok] IFF the message was handled, or is ignored,
+    /// - [Err] otherwise, which stops the node
+    async fn handle(&self, ctx: &Context<W, A>, msg: Msg<W, A>) -> Status;
+
+    /// Handle a timer scheduled via the [Context] firing
+    ///
+    /// Timers are ignored by default.
+    async fn timer(&self, _ctx: &Context<W, A>, _timer: TimerId) -> Status {
+        Ok(())
+    }
+}
+
+/// [Process] adapter running a [Node]
+///
+/// The node's handlers are either interleaved within the process' task, see
+/// [ProcNet::interleave], or each run in their own task, see [ProcNet::dispatch]. The mode and its
+/// [Concurrency] are chosen when the process is created.
+pub struct NodeProcess<W, A, N>
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    node: Arc<N>,
+    handlers: Handlers,
+    ctx: Option<Arc<Context<W, A>>>,
+}
+
+/// How a [NodeProcess] runs the node's handlers
+#[derive(Clone, Copy, Debug)]
+enum Handlers {
+    /// Interleaved within the process' task
+    Interleaved(Concurrency),
+    /// Each in its own task
+    Dispatched(Concurrency),
+}
+
+impl<W, A, N> NodeProcess<W, A, N>
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    /// Create a process handling one event at a time with `node`
+    pub fn new(node: N) -> Self {
+        Self::with_interleave(node, Default::default())
+    }
+
+    /// Create a process handling events with `node`, interleaved as limited by `config`
+    pub fn with_interleave(node: N, config: Concurrency) -> Self {
+        Self {
+            node: Arc::new(node),
+            handlers: Handlers::Interleaved(config),
+            ctx: None,
+        }
+    }
+
+    /// Create a process handling each event with `node` in its own task, as limited by `config`
+    pub fn with_dispatch(node: N, config: Concurrency) -> Self {
+        Self {
+            node: Arc::new(node),
+            handlers: Handlers::Dispatched(config),
+            ctx: None,
+        }
+    }
+
+    /// Get the node
+    pub fn node(&self) -> &N {
+        &self.node
+    }
+}
+
+#[async_trait]
+impl<W, A, N> Process<W, A> for NodeProcess<W, A, N>
+where
+    W: DeserializeOwned + Serialize + Send + Sync + 'static,
+    A: DeserializeOwned + Serialize + Send + Sync + 'static,
+    N: Node<W, A> + 'static,
+{
+    async fn init(
+        &mut self,
+        args: Vec<String>,
+        net: ProcNet<W, A>,
+        id: Id,
+        ids: Vec<Id>,
+    ) -> Status {
+        let ctx = Arc::new(Context { args, net, id, ids });
+        self.ctx = Some(ctx.clone());
+        self.node.init(&ctx).await
+    }
+
+    async fn run(&self) -> Status {
+        let ctx = self.ctx.as_ref().expect("initialized node process");
+        match self.handlers {
+            Handlers::Dispatched(config) => {
+                ctx.net
+                    .dispatch(config, |event| {
+                        let (node, ctx) = (self.node.clone(), ctx.clone());
+                        async move { Self::handle(&*node, &ctx, event).await }
+                    })
+                    .await
+            }
+            Handlers::Interleaved(config) => {
+                ctx.net
+                    .interleave(config, |event| Self::handle(&self.node, ctx, event))
+                    .await
+            }
+        }
+    }
+}
+
+impl<W, A, N> NodeProcess<W, A, N>
+where
+    W: DeserializeOwned + Serialize + Send + Sync,
+    A: DeserializeOwned + Serialize + Send + Sync,
+    N: Node<W, A>,
+{
+    /// Handle `event` with `node`
+    async fn handle(node: &N, ctx: &Context<W, A>, event: Event<W, A>) -> Status {
+        match event {
+            Event::Msg(msg) => node.handle(ctx, msg).await,
+            Event::Timer(timer) => node.timer(ctx, timer).await,
+        }
+    }
+}
+
+/// Echoes echo requests, and then sends a tick to `test` after a delay
+#[cfg(test)]
+struct EchoNode;
+
+#[cfg(test)]
+#[async_trait]
+impl Node<Echo, ()> for EchoNode {
+    async fn handle(&self, ctx: &Context<Echo, ()>, msg: Msg<Echo, ()>) -> Status {
+        match &msg.body {
+            Workload(Echo::Echo { echo, .. }) => {
+                ctx.reply(&msg, |in_reply_to| {
+                    Workload(Echo::EchoOk {
+                        in_reply_to,
+                        msg_id: Some(ctx.next_msg_id()),
+                        echo: echo.clone(),
+                    })
+                })
+                .await?;
+                ctx.schedule_once(Duration::from_millis(1));
+                Ok(())
+            }
+            _ => panic!("unexpected message {:?}", msg),
+        }
+    }
+
+    async fn timer(&self, ctx: &Context<Echo, ()>, timer: TimerId) -> Status {
+        let body = Workload(Echo::Echo {
+            msg_id: ctx.next_msg_id(),
+            echo: Value::String(format!("tick {}", timer)),
+        });
+        ctx.send("test".to_string(), body).await
+    }
+}
+
+#[test]
+async fn test_node_process() {
+    let (r, txq, rxq) =
+        new_uninitialized_for_test(NodeProcess::new(EchoNode), |builder| builder).await;
+
+    let run = r.run();
+    let check = async {
+        rxq.recv().await.expect("init_ok");
+        let echo = Msg::<Echo, ()> {
+            src: "c1".to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::Echo {
+                msg_id: 1,
+                echo: Value::String("boo!".to_string()),
+            }),
+        };
+        txq.send(serde_json::to_string(&echo).expect("serialized"))
+            .await
+            .expect("sent echo");
+        let echoed: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("echo_ok")).expect("deserialized");
+        assert_eq!(echoed.src, "a");
+        assert_eq!(echoed.dest, "c1");
+        assert!(
+            matches!(&echoed.body, Workload(Echo::EchoOk { in_reply_to: 1, echo, .. }) if echo == "boo!"),
+            "{:?}",
+            echoed
+        );
+
+        let tick: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("tick")).expect("deserialized");
+        assert_eq!(tick.dest, "test");
+        assert!(
+            matches!(&tick.body, Workload(Echo::Echo { echo, .. }) if echo == "tick 0"),
+            "{:?}",
+            tick
+        );
+        txq.close();
+    };
+    let (status, _) = futures::join!(run, check);
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+/// Echoes echo requests, replying to `wait` only once `open` is received
+#[cfg(test)]
+struct GatedEchoNode {
+    gate_txq: async_channel::Sender<()>,
+    gate_rxq: async_channel::Receiver<()>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Node<Echo, ()> for GatedEchoNode {
+    async fn handle(&self, ctx: &Context<Echo, ()>, msg: Msg<Echo, ()>) -> Status {
+        match &msg.body {
+            Workload(Echo::Echo { echo, .. }) => {
+                match echo.as_str() {
+                    Some("wait") => self.gate_rxq.recv().await.expect("gate"),
+                    Some("open") => self.gate_txq.send(()).await.expect("gate"),
+                    _ => {}
+                }
+                ctx.reply(&msg, |in_reply_to| {
+                    Workload(Echo::EchoOk {
+                        in_reply_to,
+                        msg_id: None,
+                        echo: echo.clone(),
+                    })
+                })
+                .await
+            }
+            _ => panic!("unexpected message {:?}", msg),
+        }
+    }
+}
+
+#[test]
+async fn test_node_process_dispatch() {
+    let (gate_txq, gate_rxq) = bounded(1);
+    let node = GatedEchoNode { gate_txq, gate_rxq };
+    let config = Concurrency {
+        max_concurrent: 2,
+        per_source_ordering: false,
+    };
+    let (r, txq, rxq) =
+        new_uninitialized_for_test(NodeProcess::with_dispatch(node, config), |builder| builder)
+            .await;
+
+    let run = r.run();
+    let check = async {
+        rxq.recv().await.expect("init_ok");
+        // The handler of `wait` completes only if the handler of `open` runs meanwhile
+        for (src, msg_id, echo) in [("c1", 1, "wait"), ("c2", 2, "open")] {
+            let msg = Msg::<Echo, ()> {
+                src: src.to_string(),
+                dest: "a".to_string(),
+                body: Workload(Echo::Echo {
+                    msg_id,
+                    echo: Value::String(echo.to_string()),
+                }),
+            };
+            txq.send(serde_json::to_string(&msg).expect("serialized"))
+                .await
+                .expect("sent echo");
+        }
+        let mut replied = vec![];
+        for _ in 0..2 {
+            let echoed: Msg<Echo, ()> =
+                serde_json::from_str(&rxq.recv().await.expect("echo_ok")).expect("deserialized");
+            replied.push(echoed.body.in_reply_to().expect("in_reply_to"));
+        }
+        replied.sort();
+        assert_eq!(replied, vec![1, 2]);
+        txq.close();
+    };
+    let (status, _) = futures::join!(run, check);
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+/// Application body that does not implement [MsgBody]
+#[cfg(test)]
+#[derive(serde::Deserialize, Serialize, Debug)]
+#[serde(tag = "type")]
+enum Gossip {
+    #[serde(rename = "gossip")]
+    Gossip { rumour: String },
+}
+
+/// Passes gossip on to `test`
+#[cfg(test)]
+struct GossipNode;
+
+#[cfg(test)]
+#[async_trait]
+impl Node<Echo, Gossip> for GossipNode {
+    async fn handle(&self, ctx: &Context<Echo, Gossip>, msg: Msg<Echo, Gossip>) -> Status {
+        ctx.send("test".to_string(), msg.body).await
+    }
+}
+
+#[test]
+async fn test_node_process_without_msg_body() {
+    let (r, txq, rxq) =
+        new_uninitialized_for_test(NodeProcess::new(GossipNode), |builder| builder).await;
+
+    let run = r.run();
+    let check = async {
+        rxq.recv().await.expect("init_ok");
+        txq.send(r#"{"src":"b","dest":"a","body":{"type":"gossip","rumour":"boo!"}}"#.to_string())
+            .await
+            .expect("sent gossip");
+        let gossip: Msg<Echo, Gossip> =
+            serde_json::from_str(&rxq.recv().await.expect("gossip")).expect("deserialized");
+        assert_eq!(gossip.dest, "test");
+        assert!(
+            matches!(&gossip.body, Body::Application(Gossip::Gossip { rumour }) if rumour == "boo!"),
+            "{:?}",
+            gossip
+        );
+        txq.close();
+    };
+    let (status, _) = futures::join!(run, check);
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+/// Fails to initialize
+#[cfg(test)]
+struct FailingInitNode;
+
+#[cfg(test)]
+#[async_trait]
+impl Node<Echo, ()> for FailingInitNode {
+    async fn init(&self, _ctx: &Context<Echo, ()>) -> Status {
+        Err(crate::Error::TestIO)
+    }
+
+    async fn handle(&self, _ctx: &Context<Echo, ()>, msg: Msg<Echo, ()>) -> Status {
+        panic!("unexpected message {:?}", msg)
+    }
+}
+
+#[test]
+async fn test_node_process_init_failure() {
+    let (r, _txq, rxq) =
+        new_uninitialized_for_test(NodeProcess::new(FailingInitNode), |builder| builder).await;
+
+    let status = r.run().await;
+    assert!(
+        matches!(&status, Err(crate::Error::Initialization(e)) if matches!(**e, crate::Error::TestIO)),
+        "{:?}",
+        status
+    );
+    let rsp: Msg<Echo, ()> =
+        serde_json::from_str(&rxq.recv().await.expect("init reply")).expect("deserialized");
+    assert!(
+        matches!(
+            &rsp.body,
+            Body::Error(crate::msg::Error {
+                in_reply_to: 0,
+                code: ErrorCode::Crash,
+                ..
+            })
+        ),
+        "{:?}",
+        rsp
+    );
+}
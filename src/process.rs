@@ -1,16 +1,70 @@
 //! Node process
 
-use async_std::channel::{bounded, Receiver, Sender};
+use std::any::type_name;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_channel::{bounded, Receiver, Sender};
 #[allow(unused)] // For doc
-use async_std::channel::{RecvError, SendError};
+use async_channel::{RecvError, SendError};
 use async_trait::async_trait;
+use futures::future::{select, Either};
+use futures::stream::{FuturesUnordered, StreamExt};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+#[cfg(test)]
+use serde_json::json;
+#[cfg(test)]
+use tokio::test;
 
-use crate::msg::{Msg, MsgId};
-#[allow(unused)] // For doc
+use crate::backend::{spawn, timeout};
+use crate::msg::Body;
+#[cfg(test)]
+use crate::msg::Body::Workload;
+#[cfg(test)]
+use crate::msg::Echo;
+use crate::msg::{Msg, MsgBody, MsgId, MsgIdGenerator};
+use crate::rpc::{Embed, Request};
+use crate::timer::{Timer, TimerId, Timers};
 use crate::Error;
-use crate::{Id, Status};
+use crate::Error::{DuplicateMsgId, MissingMsgId, Shutdown, Timeout};
+use crate::{Id, Result, Status};
+
+/// Time a [ProcNet::call] waits for a reply
+pub const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An event delivered to a process via its [ProcNet::rxq]
+#[derive(Debug, Eq, PartialEq)]
+pub enum Event<W, A> {
+    /// A message received from the network
+    Msg(Msg<W, A>),
+    /// A timer scheduled by the process fired
+    Timer(TimerId),
+}
+
+/// Limits on the event handlers [ProcNet::interleave] and [ProcNet::dispatch] run at once
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Concurrency {
+    /// The maximum number of handlers in progress at once
+    pub max_concurrent: usize,
+    /// Handle messages from the same source one at a time, in the order received
+    ///
+    /// Timer events are not ordered.
+    pub per_source_ordering: bool,
+}
+
+impl Default for Concurrency {
+    /// Handle one event at a time
+    fn default() -> Self {
+        Self {
+            max_concurrent: 1,
+            per_source_ordering: false,
+        }
+    }
+}
 
 /// The process' interface to the Maelstrom network
 ///
@@ -25,7 +79,15 @@ where
     /// Transmit queue
     pub txq: Sender<Msg<W, A>>,
     /// Receive queue
-    pub rxq: Receiver<Msg<W, A>>,
+    pub rxq: Receiver<Event<W, A>>,
+    /// This node's ID
+    id: Id,
+    /// RPC calls awaiting a reply
+    calls: Arc<Calls<W, A>>,
+    /// Message IDs shared with the runtime
+    msg_ids: Arc<MsgIdGenerator>,
+    /// Timers fired by the runtime
+    timers: Arc<Timers>,
 }
 
 impl<W, A> Default for ProcNet<W, A>
@@ -34,8 +96,430 @@ where
     A: DeserializeOwned + Serialize,
 {
     fn default() -> Self {
+        let (txq, _) = bounded(1);
+        let (_, rxq) = bounded(1);
+        Self {
+            txq,
+            rxq,
+            id: Default::default(),
+            calls: Default::default(),
+            msg_ids: Default::default(),
+            timers: Default::default(),
+        }
+    }
+}
+
+impl<W, A> ProcNet<W, A>
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    pub(crate) fn new(
+        id: Id,
+        txq: Sender<Msg<W, A>>,
+        rxq: Receiver<Event<W, A>>,
+        calls: Arc<Calls<W, A>>,
+        msg_ids: Arc<MsgIdGenerator>,
+        timers: Arc<Timers>,
+    ) -> Self {
+        Self {
+            txq,
+            rxq,
+            id,
+            calls,
+            msg_ids,
+            timers,
+        }
+    }
+
+    /// Receive events, and handle each with its own invocation of `handler`
+    ///
+    /// Handlers are interleaved cooperatively within the calling task, up to
+    /// [Concurrency::max_concurrent] at once. No handler task is spawned, so handlers may borrow
+    /// the process, but they never run in parallel: a handler awaiting a [Self::call] reply lets
+    /// the others progress, while a handler blocking its thread blocks them all. Once the limit is
+    /// reached no more events are received until a handler completes.
+    ///
+    /// Return
+    /// - [Ok] IFF the receive queue closed and all handlers completed successfully,
+    /// - [Err] the first handler failure. Other running handlers are dropped.
+    pub async fn interleave<F, Fut>(&self, config: Concurrency, handler: F) -> Status
+    where
+        F: Fn(Event<W, A>) -> Fut,
+        Fut: Future<Output = Status>,
+    {
+        self.handle_events(config, handler).await
+    }
+
+    /// Receive events, and handle each with its own invocation of `handler` in a spawned task
+    ///
+    /// Each handler runs as a task on the executor of the selected backend, so handlers make
+    /// progress at the same time, and in parallel on a multi-threaded executor. Up to
+    /// [Concurrency::max_concurrent] handler tasks run at once. Once the limit is reached no more
+    /// events are received until a handler task completes.
+    ///
+    /// Return
+    /// - [Ok] IFF the receive queue closed and all handlers completed successfully,
+    /// - [Err] the first handler failure. Other running handler tasks are cancelled.
+    pub async fn dispatch<F, Fut>(&self, config: Concurrency, handler: F) -> Status
+    where
+        F: Fn(Event<W, A>) -> Fut,
+        Fut: Future<Output = Status> + Send + 'static,
+    {
+        self.handle_events(config, |event| spawn(handler(event)))
+            .await
+    }
+
+    /// Receive events, and handle each with its own handler future
+    ///
+    /// At most [Concurrency::max_concurrent] handler futures are in progress at once, with their
+    /// sources' messages queued in order when [Concurrency::per_source_ordering] is set.
+    async fn handle_events<F, Fut>(&self, config: Concurrency, handler: F) -> Status
+    where
+        F: Fn(Event<W, A>) -> Fut,
+        Fut: Future<Output = Status>,
+    {
+        let limit = config.max_concurrent.max(1);
+        let handle = |source: Option<Id>, event| {
+            let handled = handler(event);
+            async move { (source, handled.await) }
+        };
+        let mut running = FuturesUnordered::new();
+        // Sources with a running handler, and their events waiting to be handled
+        let mut busy: HashMap<Id, VecDeque<Event<W, A>>> = HashMap::new();
+        let mut waiting = 0;
+        let mut receiving = true;
+        loop {
+            let next = if receiving && running.is_empty() {
+                // An empty set of running handlers is always complete
+                Either::Left(self.rxq.recv().await.ok())
+            } else if receiving && running.len() + waiting < limit {
+                match select(pin!(self.rxq.recv()), running.next()).await {
+                    Either::Left((received, _)) => Either::Left(received.ok()),
+                    Either::Right((handled, _)) => Either::Right(handled),
+                }
+            } else {
+                Either::Right(running.next().await)
+            };
+            match next {
+                Either::Left(Some(event)) => {
+                    let source = match &event {
+                        Event::Msg(msg) if config.per_source_ordering => Some(msg.src.clone()),
+                        _ => None,
+                    };
+                    match source {
+                        Some(src) if busy.contains_key(&src) => {
+                            busy.get_mut(&src).expect("busy").push_back(event);
+                            waiting += 1;
+                        }
+                        Some(src) => {
+                            busy.insert(src.clone(), VecDeque::new());
+                            running.push(handle(Some(src), event));
+                        }
+                        None => running.push(handle(None, event)),
+                    }
+                }
+                Either::Left(None) => receiving = false,
+                Either::Right(Some((source, status))) => {
+                    status?;
+                    if let Some(src) = source {
+                        match busy.get_mut(&src).and_then(VecDeque::pop_front) {
+                            Some(event) => {
+                                waiting -= 1;
+                                running.push(handle(Some(src), event));
+                            }
+                            None => {
+                                busy.remove(&src);
+                            }
+                        }
+                    }
+                }
+                Either::Right(None) if receiving => {}
+                Either::Right(None) => return Ok(()),
+            }
+        }
+    }
+
+    /// Schedule an [Event::Timer] to be delivered once, after `delay`
+    ///
+    /// Timer events are sent straight to [Self::rxq] when they fire, so they may overtake
+    /// messages held back by [OverflowPolicy::Block](crate::runtime::OverflowPolicy::Block).
+    /// Like messages, they may be dropped from a full queue by
+    /// [OverflowPolicy::DropOldest](crate::runtime::OverflowPolicy::DropOldest).
+    pub fn schedule_once(&self, delay: Duration) -> Timer {
+        Timers::schedule(&self.timers, delay, None)
+    }
+
+    /// Schedule an [Event::Timer] to be delivered every `period`, until cancelled
+    ///
+    /// Periods missed, e.g. because the receive queue was full, are skipped. Events are
+    /// delivered as for [Self::schedule_once].
+    pub fn schedule_periodic(&self, period: Duration) -> Timer {
+        Timers::schedule(&self.timers, period, Some(period))
+    }
+
+    /// Get the next unique message ID
+    ///
+    /// IDs are allocated from a generator shared with the runtime, so they do not collide with
+    /// IDs used by the runtime, e.g. for the initialization response.
+    pub fn next_msg_id(&self) -> MsgId {
+        self.msg_ids.next_msg_id()
+    }
+
+    /// Send a request and wait up to [DEFAULT_RPC_TIMEOUT] for its reply
+    ///
+    /// See [Self::call_with_timeout].
+    pub async fn call(&self, dest: Id, body: Body<W, A>) -> Result<Msg<W, A>>
+    where
+        W: MsgBody,
+        A: MsgBody,
+    {
+        self.call_with_timeout(dest, body, DEFAULT_RPC_TIMEOUT)
+            .await
+    }
+
+    /// Send a request and wait for its reply
+    ///
+    /// The request `body` must have a `msg_id`. The runtime delivers the reply, i.e. the message
+    /// from `dest` whose `in_reply_to` matches the request's `msg_id`, to the caller instead of
+    /// [Self::rxq]. A reply arriving after the call has returned is delivered to [Self::rxq].
+    ///
+    /// Return
+    /// - [Ok] the reply, which may have an [Error](crate::msg::Error) body
+    /// - [Err]:[Error::MissingMsgId] IFF the request has no `msg_id`
+    /// - [Err]:[Error::DuplicateMsgId] IFF another call with the `msg_id` awaits its reply
+    /// - [Err]:[Error::Timeout] IFF the request was not sent and replied to within `timeout`
+    /// - [Err]:[Error::Shutdown] IFF the runtime has shutdown
+    /// - [Err] otherwise
+    pub async fn call_with_timeout(
+        &self,
+        dest: Id,
+        body: Body<W, A>,
+        timeout: Duration,
+    ) -> Result<Msg<W, A>>
+    where
+        W: MsgBody,
+        A: MsgBody,
+    {
+        let msg_id = body.msg_id().ok_or(MissingMsgId)?;
+        let call = Calls::register(&self.calls, &dest, msg_id)?;
+        let request = Msg {
+            src: self.id.clone(),
+            dest,
+            body,
+        };
+        // The timeout includes waiting for room in a full egress queue
+        let started = Instant::now();
+        match crate::backend::timeout(timeout, self.txq.send(request)).await {
+            Some(sent) => sent?,
+            None => return Err(Timeout),
+        }
+        call.reply(timeout.saturating_sub(started.elapsed())).await
+    }
+
+    /// Send a typed request and wait up to [DEFAULT_RPC_TIMEOUT] for its response
+    ///
+    /// See [Self::request_with_timeout].
+    pub async fn request<R, V>(
+        &self,
+        dest: Id,
+        request: R,
+    ) -> std::result::Result<R::Response, R::Error>
+    where
+        W: MsgBody,
+        A: MsgBody,
+        R: Request,
+        Body<W, A>: Embed<R::Body, V>,
+    {
+        self.request_with_timeout(dest, request, DEFAULT_RPC_TIMEOUT)
+            .await
+    }
+
+    /// Send a typed request and wait for its response
+    ///
+    /// The request is sent in the [Body] variant carrying its [Request::Body], see [Embed].
+    ///
+    /// Return
+    /// - [Ok] the response
+    /// - [Err] the request's error for an [Error](crate::msg::Error) reply, a reply that is not
+    ///   the response ([Error::UnexpectedMsg]), or a failed call, see [Self::call_with_timeout]
+    pub async fn request_with_timeout<R, V>(
+        &self,
+        dest: Id,
+        request: R,
+        timeout: Duration,
+    ) -> std::result::Result<R::Response, R::Error>
+    where
+        W: MsgBody,
+        A: MsgBody,
+        R: Request,
+        Body<W, A>: Embed<R::Body, V>,
+    {
+        let body = Body::embed(request.into_body(self.next_msg_id()));
+        match self.call_with_timeout(dest, body, timeout).await?.body {
+            Body::Error(e) => Err(e.into()),
+            body => body
+                .extract()
+                .and_then(|body| R::response(body).ok())
+                .ok_or_else(|| {
+                    Error::UnexpectedMsg {
+                        expected: type_name::<R::Response>(),
+                    }
+                    .into()
+                }),
+        }
+    }
+}
+
+/// RPC calls awaiting a reply, keyed by request message ID
+pub(crate) struct Calls<W, A> {
+    table: Mutex<CallTable<W, A>>,
+    /// Wakes [Calls::registered] when a call is registered
+    wake_txq: Sender<()>,
+    wake_rxq: Receiver<()>,
+}
+
+struct CallTable<W, A> {
+    closed: bool,
+    /// Identifies the next registered call
+    next_call: u64,
+    pending: HashMap<MsgId, Waiting<W, A>>,
+}
+
+/// A call waiting for its reply
+struct Waiting<W, A> {
+    call: u64,
+    /// The request's destination, which must send the reply
+    dest: Id,
+    txq: Sender<Msg<W, A>>,
+}
+
+impl<W, A> Default for Calls<W, A> {
+    fn default() -> Self {
+        let (wake_txq, wake_rxq) = bounded(1);
+        Self {
+            table: Mutex::new(CallTable {
+                closed: false,
+                next_call: 0,
+                pending: HashMap::new(),
+            }),
+            wake_txq,
+            wake_rxq,
+        }
+    }
+}
+
+impl<W, A> Calls<W, A> {
+    /// Register a call awaiting a reply from `dest` to `msg_id`
+    ///
+    /// Return
+    /// - [Ok] the call,
+    /// - [Err]:[Error::DuplicateMsgId] IFF another call awaits a reply to `msg_id`,
+    /// - [Err]:[Error::Shutdown] IFF the runtime has shutdown
+    fn register(calls: &Arc<Self>, dest: &Id, msg_id: MsgId) -> Result<PendingCall<W, A>> {
         let (txq, rxq) = bounded(1);
-        Self { txq, rxq }
+        let mut table = calls.table.lock().expect("calls lock");
+        if table.closed {
+            return Err(Shutdown);
+        }
+        if table.pending.contains_key(&msg_id) {
+            return Err(DuplicateMsgId);
+        }
+        let call = table.next_call;
+        table.next_call += 1;
+        table.pending.insert(
+            msg_id,
+            Waiting {
+                call,
+                dest: dest.clone(),
+                txq,
+            },
+        );
+        // A pending wake up is as good as a new one
+        let _ = calls.wake_txq.try_send(());
+        Ok(PendingCall {
+            call,
+            msg_id,
+            rxq,
+            calls: calls.clone(),
+        })
+    }
+
+    /// Deliver a reply to its waiting call
+    ///
+    /// A message is a call's reply IFF it is from the call's destination, and `in_reply_to` the
+    /// call's request. The runtime reads `in_reply_to` from the received message, so bodies need
+    /// not implement [MsgBody] to be routed.
+    ///
+    /// Return the message IFF no call is waiting for it
+    pub(crate) fn route(&self, msg: Msg<W, A>, in_reply_to: Option<MsgId>) -> Option<Msg<W, A>> {
+        let waiting = in_reply_to.and_then(|id| {
+            let mut table = self.table.lock().expect("calls lock");
+            match table.pending.get(&id) {
+                Some(waiting) if waiting.dest == msg.src => table.pending.remove(&id),
+                _ => None,
+            }
+        });
+        match waiting {
+            Some(waiting) => waiting.txq.try_send(msg).err().map(|e| e.into_inner()),
+            None => Some(msg),
+        }
+    }
+
+    /// Is any call waiting for its reply?
+    pub(crate) fn is_waiting(&self) -> bool {
+        !self.table.lock().expect("calls lock").pending.is_empty()
+    }
+
+    /// Wait until a call may have been registered since the last wait
+    pub(crate) async fn registered(&self) {
+        let _ = self.wake_rxq.recv().await;
+    }
+
+    /// Fail all waiting and future calls with [Error::Shutdown]
+    pub(crate) fn close(&self) {
+        let mut table = self.table.lock().expect("calls lock");
+        table.closed = true;
+        table.pending.clear();
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.table.lock().expect("calls lock").pending.len()
+    }
+}
+
+/// A registered call
+///
+/// The call is deregistered when dropped, so abandoned calls do not leak.
+struct PendingCall<W, A> {
+    call: u64,
+    msg_id: MsgId,
+    rxq: Receiver<Msg<W, A>>,
+    calls: Arc<Calls<W, A>>,
+}
+
+impl<W, A> PendingCall<W, A> {
+    async fn reply(&self, t: Duration) -> Result<Msg<W, A>> {
+        match timeout(t, self.rxq.recv()).await {
+            Some(reply) => Ok(reply?),
+            None => Err(Timeout),
+        }
+    }
+}
+
+impl<W, A> Drop for PendingCall<W, A> {
+    fn drop(&mut self) {
+        if let Ok(mut table) = self.calls.table.lock() {
+            // The msg_id may have been reused by a later call, once this call was replied to
+            if table
+                .pending
+                .get(&self.msg_id)
+                .is_some_and(|waiting| waiting.call == self.call)
+            {
+                table.pending.remove(&self.msg_id);
+            }
+        }
     }
 }
 
@@ -55,22 +539,23 @@ where
     W: DeserializeOwned + Serialize,
     A: DeserializeOwned + Serialize,
 {
-    /// Create a process
+    /// Initialize the process
+    ///
+    /// The runtime initializes the process before replying to Maelstrom's `init` message, and
+    /// replies with an [ErrorCode::Crash](crate::ErrorCode::Crash) error instead of `init_ok` if
+    /// initialization fails. IO runs meanwhile, so the process may call other nodes and services,
+    /// e.g. via [ProcNet::request]. Other messages received meanwhile are queued for [Self::run].
     ///
     /// - `args` pass through command line args
     /// - `net` a network interface to Maelstrom
     /// - `id` this node's ID
     /// - `ids` all protocol participants' IDs
-    /// - `start_msg_id` the first message ID to use. Initialization messages are handled by the
-    ///   runtime, so this may be greater than 0.
-    fn init(
-        &mut self,
-        args: Vec<String>,
-        net: ProcNet<W, A>,
-        id: Id,
-        ids: Vec<Id>,
-        start_msg_id: MsgId,
-    );
+    ///
+    /// Return
+    /// - [Ok] IFF the process was initialized,
+    /// - [Err] otherwise
+    async fn init(&mut self, args: Vec<String>, net: ProcNet<W, A>, id: Id, ids: Vec<Id>)
+        -> Status;
 
     /// Run the process
     ///
@@ -82,3 +567,531 @@ where
     /// - [Err] otherwise
     async fn run(&self) -> Status;
 }
+
+#[test]
+async fn test_call_reply() {
+    let (txq, erxq) = bounded(10);
+    let (_etxq, rxq) = bounded(10);
+    let calls: Arc<Calls<Echo, ()>> = Default::default();
+    let net = ProcNet::new(
+        "a".to_string(),
+        txq,
+        rxq,
+        calls.clone(),
+        Default::default(),
+        Default::default(),
+    );
+
+    let request = Workload(Echo::Echo {
+        msg_id: 7,
+        echo: json!("ping"),
+    });
+    let call = net.call("b".to_string(), request);
+    let reply = async {
+        let request = erxq.recv().await.expect("request");
+        assert_eq!(request.src, "a");
+        assert_eq!(request.dest, "b");
+        assert_eq!(calls.len(), 1);
+        // A message that is not a reply is not routed to the call
+        let other = Msg {
+            src: "b".to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::Echo {
+                msg_id: 7,
+                echo: json!("other"),
+            }),
+        };
+        assert!(calls.route(other, None).is_some());
+        let reply = Msg {
+            src: "b".to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::EchoOk {
+                in_reply_to: 7,
+                msg_id: None,
+                echo: json!("pong"),
+            }),
+        };
+        assert!(calls.route(reply, Some(7)).is_none());
+    };
+    let (reply, _) = tokio::join!(call, reply);
+    let reply = reply.expect("reply");
+    assert!(matches!(
+        reply.body,
+        Workload(Echo::EchoOk { in_reply_to: 7, .. })
+    ));
+    assert_eq!(calls.len(), 0);
+}
+
+#[test]
+async fn test_call_timeout() {
+    let (txq, _erxq) = bounded(10);
+    let (_etxq, rxq) = bounded(10);
+    let calls: Arc<Calls<Echo, ()>> = Default::default();
+    let net = ProcNet::new(
+        "a".to_string(),
+        txq,
+        rxq,
+        calls.clone(),
+        Default::default(),
+        Default::default(),
+    );
+
+    let request = Workload(Echo::Echo {
+        msg_id: 7,
+        echo: json!("ping"),
+    });
+    let result = net
+        .call_with_timeout("b".to_string(), request, Duration::from_millis(10))
+        .await;
+    assert!(matches!(result, Err(Timeout)), "{:?}", result);
+    assert_eq!(calls.len(), 0);
+
+    // A late reply is passed on to the process
+    let late = Msg {
+        src: "b".to_string(),
+        dest: "a".to_string(),
+        body: Workload(Echo::EchoOk {
+            in_reply_to: 7,
+            msg_id: None,
+            echo: json!("pong"),
+        }),
+    };
+    assert!(calls.route(late, Some(7)).is_some());
+
+    // Calls fail once the runtime has shutdown
+    calls.close();
+    let request = Workload(Echo::Echo {
+        msg_id: 8,
+        echo: json!("ping"),
+    });
+    let result = net.call("b".to_string(), request).await;
+    assert!(matches!(result, Err(Shutdown)), "{:?}", result);
+}
+
+#[test]
+async fn test_call_timeout_on_full_egress() {
+    let (txq, _erxq) = bounded(1);
+    let (_etxq, rxq) = bounded(10);
+    let calls: Arc<Calls<Echo, ()>> = Default::default();
+    let net = ProcNet::new(
+        "a".to_string(),
+        txq,
+        rxq,
+        calls.clone(),
+        Default::default(),
+        Default::default(),
+    );
+
+    // The first request fills the egress queue, so the second cannot be sent in time
+    let request = |msg_id| {
+        Workload(Echo::Echo {
+            msg_id,
+            echo: json!("ping"),
+        })
+    };
+    let result = net
+        .call_with_timeout("b".to_string(), request(7), Duration::from_millis(10))
+        .await;
+    assert!(matches!(result, Err(Timeout)), "{:?}", result);
+    let result = net
+        .call_with_timeout("b".to_string(), request(8), Duration::from_millis(10))
+        .await;
+    assert!(matches!(result, Err(Timeout)), "{:?}", result);
+    assert_eq!(calls.len(), 0);
+}
+
+#[test]
+async fn test_call_reply_from_other_node() {
+    let (txq, erxq) = bounded(10);
+    let (_etxq, rxq) = bounded(10);
+    let calls: Arc<Calls<Echo, ()>> = Default::default();
+    let net = ProcNet::new(
+        "a".to_string(),
+        txq,
+        rxq,
+        calls.clone(),
+        Default::default(),
+        Default::default(),
+    );
+
+    let request = Workload(Echo::Echo {
+        msg_id: 7,
+        echo: json!("ping"),
+    });
+    let call = net.call("b".to_string(), request);
+    let reply = async {
+        erxq.recv().await.expect("request");
+        let reply = |src: &str| Msg {
+            src: src.to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::EchoOk {
+                in_reply_to: 7,
+                msg_id: None,
+                echo: json!(src),
+            }),
+        };
+        // Only the request's destination can reply
+        assert!(calls.route(reply("c"), Some(7)).is_some());
+        assert_eq!(calls.len(), 1);
+        assert!(calls.route(reply("b"), Some(7)).is_none());
+    };
+    let (reply, _) = tokio::join!(call, reply);
+    let reply = reply.expect("reply");
+    assert_eq!(reply.src, "b");
+    assert_eq!(calls.len(), 0);
+}
+
+#[test]
+async fn test_call_duplicate_msg_id() {
+    let calls: Arc<Calls<Echo, ()>> = Default::default();
+    let first = Calls::register(&calls, &"b".to_string(), 7).expect("registered");
+    let duplicate = Calls::register(&calls, &"c".to_string(), 7);
+    assert!(matches!(duplicate, Err(DuplicateMsgId)));
+    assert_eq!(calls.len(), 1);
+
+    // The first call still gets its reply
+    let reply = Msg {
+        src: "b".to_string(),
+        dest: "a".to_string(),
+        body: Workload(Echo::EchoOk {
+            in_reply_to: 7,
+            msg_id: None,
+            echo: json!("pong"),
+        }),
+    };
+    assert!(calls.route(reply, Some(7)).is_none());
+    let reply = first.reply(Duration::from_millis(10)).await.expect("reply");
+    assert_eq!(reply.src, "b");
+
+    // Once replied to, the msg_id may be reused, and dropping the first call keeps the new one
+    let second = Calls::register(&calls, &"c".to_string(), 7).expect("registered");
+    drop(first);
+    assert_eq!(calls.len(), 1);
+    drop(second);
+    assert_eq!(calls.len(), 0);
+}
+
+#[test]
+async fn test_call_without_msg_id() {
+    let net: ProcNet<Echo, ()> = Default::default();
+    let request = Workload(Echo::EchoOk {
+        in_reply_to: 7,
+        msg_id: None,
+        echo: json!("ping"),
+    });
+    let result = net.call("b".to_string(), request).await;
+    assert!(matches!(result, Err(MissingMsgId)), "{:?}", result);
+}
+
+#[cfg(test)]
+fn echo_event(src: &str, msg_id: MsgId) -> Event<Echo, ()> {
+    Event::Msg(Msg {
+        src: src.to_string(),
+        dest: "a".to_string(),
+        body: Workload(Echo::Echo {
+            msg_id,
+            echo: json!(msg_id),
+        }),
+    })
+}
+
+#[cfg(test)]
+fn echo_event_msg_id(event: &Event<Echo, ()>) -> MsgId {
+    match event {
+        Event::Msg(Msg {
+            body: Workload(Echo::Echo { msg_id, .. }),
+            ..
+        }) => *msg_id,
+        _ => panic!("unexpected event {:?}", event),
+    }
+}
+
+/// Handle events where the handler of message 1 waits for the handler of message 3
+///
+/// Handlers are dispatched to spawned tasks IFF `spawned`, and interleaved otherwise.
+///
+/// Return the messages in the order their handlers completed
+#[cfg(test)]
+async fn handle_gated(per_source_ordering: bool, spawned: bool) -> Vec<MsgId> {
+    let (txq, rxq) = bounded(10);
+    let config = Concurrency {
+        max_concurrent: 4,
+        per_source_ordering,
+    };
+    let net: ProcNet<Echo, ()> = ProcNet {
+        rxq,
+        ..Default::default()
+    };
+    for (src, msg_id) in [("c1", 1), ("c1", 2), ("c2", 3)] {
+        txq.send(echo_event(src, msg_id)).await.expect("sent");
+    }
+    txq.close();
+
+    let (gate_txq, gate_rxq) = bounded(1);
+    let handled = Arc::new(Mutex::new(vec![]));
+    let handler = |event: Event<Echo, ()>| {
+        let msg_id = echo_event_msg_id(&event);
+        let (gate_txq, gate_rxq, handled) = (gate_txq.clone(), gate_rxq.clone(), handled.clone());
+        async move {
+            match msg_id {
+                1 => gate_rxq.recv().await.expect("gate"),
+                3 => gate_txq.send(()).await.expect("gate"),
+                _ => {}
+            }
+            handled.lock().expect("handled").push(msg_id);
+            Ok(())
+        }
+    };
+    let status = match spawned {
+        true => net.dispatch(config, handler).await,
+        false => net.interleave(config, handler).await,
+    };
+    assert!(status.is_ok(), "{:?}", status);
+    let handled = handled.lock().expect("handled").clone();
+    handled
+}
+
+#[test]
+async fn test_interleave_concurrent() {
+    assert_eq!(handle_gated(false, false).await, vec![2, 3, 1]);
+}
+
+#[test]
+async fn test_interleave_per_source_ordering() {
+    assert_eq!(handle_gated(true, false).await, vec![3, 1, 2]);
+}
+
+#[test]
+async fn test_dispatch_concurrent() {
+    // Handler tasks may complete in any order, but 1 only completes if 3 runs meanwhile
+    let mut handled = handle_gated(false, true).await;
+    handled.sort();
+    assert_eq!(handled, vec![1, 2, 3]);
+}
+
+#[test]
+async fn test_dispatch_per_source_ordering() {
+    let handled = handle_gated(true, true).await;
+    let position = |msg_id| handled.iter().position(|m| *m == msg_id).expect("handled");
+    assert_eq!(handled.len(), 3);
+    assert!(position(3) < position(1), "{:?}", handled);
+    assert!(position(1) < position(2), "{:?}", handled);
+}
+
+#[test]
+async fn test_interleave_max_concurrent() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::SeqCst;
+
+    let (txq, rxq) = bounded(10);
+    let net: ProcNet<Echo, ()> = ProcNet {
+        rxq,
+        ..Default::default()
+    };
+    for msg_id in 0..8 {
+        txq.send(echo_event("c1", msg_id)).await.expect("sent");
+    }
+    txq.close();
+
+    let running = AtomicUsize::new(0);
+    let max_running = AtomicUsize::new(0);
+    let config = Concurrency {
+        max_concurrent: 3,
+        per_source_ordering: false,
+    };
+    net.interleave(config, |_| async {
+        let n = running.fetch_add(1, SeqCst) + 1;
+        max_running.fetch_max(n, SeqCst);
+        crate::backend::sleep(Duration::from_millis(2)).await;
+        running.fetch_sub(1, SeqCst);
+        Ok(())
+    })
+    .await
+    .expect("interleaved");
+    assert_eq!(max_running.load(SeqCst), 3);
+}
+
+#[test]
+async fn test_interleave_handler_failure() {
+    let (txq, rxq) = bounded(10);
+    let net: ProcNet<Echo, ()> = ProcNet {
+        rxq,
+        ..Default::default()
+    };
+    txq.send(echo_event("c1", 1)).await.expect("sent");
+    let status = net
+        .interleave(Default::default(), |_| async {
+            Err(Error::UnexpectedMsg {
+                expected: "nothing",
+            })
+        })
+        .await;
+    assert!(
+        matches!(status, Err(Error::UnexpectedMsg { .. })),
+        "{:?}",
+        status
+    );
+}
+
+#[test]
+async fn test_dispatch_failure_cancels_handler_tasks() {
+    let (txq, rxq) = bounded(10);
+    let net: ProcNet<Echo, ()> = ProcNet {
+        rxq,
+        ..Default::default()
+    };
+    for msg_id in [1, 2] {
+        txq.send(echo_event("c1", msg_id)).await.expect("sent");
+    }
+
+    // The handler of message 1 holds the gate until cancelled, then message 2 fails
+    let (gate_txq, gate_rxq) = bounded::<()>(1);
+    let config = Concurrency {
+        max_concurrent: 2,
+        per_source_ordering: false,
+    };
+    let status = net
+        .dispatch(config, |event| {
+            let gate_rxq = gate_rxq.clone();
+            async move {
+                match echo_event_msg_id(&event) {
+                    1 => {
+                        let _gate = gate_rxq;
+                        futures::future::pending().await
+                    }
+                    _ => Err(Error::UnexpectedMsg {
+                        expected: "nothing",
+                    }),
+                }
+            }
+        })
+        .await;
+    assert!(
+        matches!(status, Err(Error::UnexpectedMsg { .. })),
+        "{:?}",
+        status
+    );
+
+    // The gate closes once the cancelled handler task drops its receiver
+    drop(gate_rxq);
+    let closed = timeout(Duration::from_secs(1), async {
+        while !gate_txq.is_closed() {
+            crate::backend::sleep(Duration::from_millis(1)).await;
+        }
+    })
+    .await;
+    assert!(closed.is_some(), "handler task not cancelled");
+}
+
+#[test]
+async fn test_interleave_waits_for_events() {
+    let (txq, rxq) = bounded(10);
+    let net: ProcNet<Echo, ()> = ProcNet {
+        rxq,
+        ..Default::default()
+    };
+    let handled = Mutex::new(vec![]);
+    let interleave = net.interleave(Default::default(), |event| {
+        let handled = &handled;
+        async move {
+            handled
+                .lock()
+                .expect("handled")
+                .push(echo_event_msg_id(&event));
+            Ok(())
+        }
+    });
+    let send = async {
+        for msg_id in 0..3 {
+            crate::backend::sleep(Duration::from_millis(2)).await;
+            txq.send(echo_event("c1", msg_id)).await.expect("sent");
+        }
+        txq.close();
+    };
+    let (status, _) = futures::join!(interleave, send);
+    assert!(status.is_ok(), "{:?}", status);
+    assert_eq!(handled.into_inner().expect("handled"), vec![0, 1, 2]);
+}
+
+#[test]
+async fn test_request() {
+    use crate::msg::LinKv;
+    use crate::rpc::{LinKvRead, LinKvReadOk};
+
+    let (txq, erxq) = bounded(10);
+    let (_etxq, rxq) = bounded(10);
+    let calls: Arc<Calls<Echo, LinKv>> = Default::default();
+    let net = ProcNet::new(
+        "a".to_string(),
+        txq,
+        rxq,
+        calls.clone(),
+        Default::default(),
+        Default::default(),
+    );
+
+    // Answer each request with the reply for its msg_id
+    let replies = [
+        Body::Application(LinKv::ReadOk {
+            in_reply_to: 0,
+            msg_id: None,
+            value: json!(3),
+        }),
+        Body::Error(crate::msg::Error {
+            in_reply_to: 1,
+            code: crate::ErrorCode::KeyDoesNotExist,
+            text: "no key".to_string(),
+        }),
+        Body::Application(LinKv::WriteOk {
+            in_reply_to: 2,
+            msg_id: None,
+        }),
+    ];
+    let reply = async {
+        for body in replies {
+            let request = erxq.recv().await.expect("request");
+            assert!(
+                matches!(request.body, Body::Application(LinKv::Read { .. })),
+                "{:?}",
+                request
+            );
+            let msg_id = request.msg_id().expect("msg_id");
+            assert_eq!(body.in_reply_to(), Some(msg_id));
+            let reply = Msg {
+                src: "lin-kv".to_string(),
+                dest: "a".to_string(),
+                body,
+            };
+            assert!(calls.route(reply, Some(msg_id)).is_none());
+        }
+    };
+    let requests = async {
+        let read = || LinKvRead { key: json!(1) };
+        let response = net.request("lin-kv".to_string(), read()).await;
+        assert!(
+            matches!(&response, Ok(LinKvReadOk { in_reply_to: 0, value, .. }) if value == &json!(3)),
+            "{:?}",
+            response
+        );
+        let response = net.request("lin-kv".to_string(), read()).await;
+        assert!(
+            matches!(&response, Err(Error::Reply(e)) if e.code == crate::ErrorCode::KeyDoesNotExist),
+            "{:?}",
+            response
+        );
+        let response = net.request("lin-kv".to_string(), read()).await;
+        assert!(
+            matches!(&response, Err(Error::UnexpectedMsg { .. })),
+            "{:?}",
+            response
+        );
+    };
+    tokio::join!(requests, reply);
+
+    // No more replies
+    let timeout = Duration::from_millis(10);
+    let response = net
+        .request_with_timeout("lin-kv".to_string(), LinKvRead { key: json!(1) }, timeout)
+        .await;
+    assert!(matches!(&response, Err(Timeout)), "{:?}", response);
+}
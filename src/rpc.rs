@@ -0,0 +1,298 @@
+//! Typed RPC requests
+//!
+//! A [Request] pairs a request with its response type, so that
+//! [ProcNet::request](crate::process::ProcNet::request) returns the response rather than a
+//! generic reply [Msg](crate::msg::Msg), e.g.
+//! ```no_run
+//! # use async_maelstrom::msg::{LinKv, LIN_KV};
+//! # use async_maelstrom::process::ProcNet;
+//! # use async_maelstrom::rpc::{LinKvRead, LinKvReadOk};
+//! # use async_maelstrom::{Key, Result, Val};
+//! # async fn read(net: &ProcNet<(), LinKv>, key: Key) -> Result<Val> {
+//! let LinKvReadOk { value, .. } = net.request(LIN_KV.to_string(), LinKvRead { key }).await?;
+//! # Ok(value)
+//! # }
+//! ```
+//! Requests are implemented for all built-in workload and service requests.
+use std::collections::HashMap;
+
+#[cfg(test)]
+use serde_json::json;
+
+use crate::msg::{
+    Body, Broadcast, Echo, GCounter, GSet, Kafka, LinKv, ListAppendOp, LogKey, LogRecord, MsgId,
+    Offset, PnCounter, RwRegisterOp, TxnListAppend, TxnRwRegister, UniqueIds,
+};
+use crate::{msg, Error, Id, Key, Val};
+
+/// Request with a typed response
+pub trait Request: Sized {
+    /// The body type carrying the request and its response, e.g. [LinKv]
+    type Body;
+    /// The response to the request
+    type Response;
+    /// The error a failed request returns
+    ///
+    /// [msg::Error] replies, and failures of the request itself, e.g. a timeout, convert into it.
+    type Error: From<msg::Error> + From<Error>;
+
+    /// Create the request body with `msg_id`
+    fn into_body(self, msg_id: MsgId) -> Self::Body;
+
+    /// Get the response from a reply `body`
+    ///
+    /// Return
+    /// - [Ok] the response IFF the body is this request's response,
+    /// - [Err] the body otherwise
+    fn response(body: Self::Body) -> std::result::Result<Self::Response, Self::Body>;
+}
+
+/// [Body] variant carrying `B` bodies
+///
+/// `V` is either [WorkloadBody] or [ApplicationBody]. It need not be named, as it is inferred
+/// from which of the body's type parameters is `B`.
+pub trait Embed<B, V> {
+    /// Create a body carrying `body`
+    fn embed(body: B) -> Self;
+
+    /// Get the carried body
+    ///
+    /// Return [None] IFF the body carries another variant
+    fn extract(self) -> Option<B>;
+}
+
+/// Marks [Body::Workload] as the [Embed] variant
+pub enum WorkloadBody {}
+
+/// Marks [Body::Application] as the [Embed] variant
+pub enum ApplicationBody {}
+
+impl<W, A> Embed<W, WorkloadBody> for Body<W, A> {
+    fn embed(body: W) -> Self {
+        Body::Workload(body)
+    }
+
+    fn extract(self) -> Option<W> {
+        match self {
+            Body::Workload(body) => Some(body),
+            _ => None,
+        }
+    }
+}
+
+impl<W, A> Embed<A, ApplicationBody> for Body<W, A> {
+    fn embed(body: A) -> Self {
+        Body::Application(body)
+    }
+
+    fn extract(self) -> Option<A> {
+        match self {
+            Body::Application(body) => Some(body),
+            _ => None,
+        }
+    }
+}
+
+/// Define a built-in request, its response, and their [Request] implementation
+///
+/// The request's fields are those of its body variant, except the `msg_id`. The response's
+/// fields are those of its body variant.
+macro_rules! request {
+    (
+        $(#[$doc:meta])*
+        $request:ident => $response:ident,
+        $body:ident::$req_variant:ident { $($req_field:ident: $req_ty:ty),* $(,)? }
+        => $rsp_variant:ident { $($rsp_field:ident: $rsp_ty:ty),* $(,)? }
+    ) => {
+        $(#[$doc])*
+        #[derive(Clone, Debug, Eq, PartialEq)]
+        pub struct $request {
+            $(pub $req_field: $req_ty,)*
+        }
+
+        #[doc = concat!("Response to a [", stringify!($request), "]")]
+        #[derive(Clone, Debug, Eq, PartialEq)]
+        pub struct $response {
+            pub in_reply_to: MsgId,
+            pub msg_id: Option<MsgId>,
+            $(pub $rsp_field: $rsp_ty,)*
+        }
+
+        impl Request for $request {
+            type Body = $body;
+            type Response = $response;
+            type Error = Error;
+
+            fn into_body(self, msg_id: MsgId) -> $body {
+                $body::$req_variant {
+                    msg_id,
+                    $($req_field: self.$req_field,)*
+                }
+            }
+
+            fn response(body: $body) -> std::result::Result<$response, $body> {
+                match body {
+                    $body::$rsp_variant {
+                        in_reply_to,
+                        msg_id,
+                        $($rsp_field,)*
+                    } => Ok($response {
+                        in_reply_to,
+                        msg_id,
+                        $($rsp_field,)*
+                    }),
+                    body => Err(body),
+                }
+            }
+        }
+    };
+}
+
+request!(
+    /// [Echo::Echo] request
+    EchoRequest => EchoResponse,
+    Echo::Echo { echo: Val } => EchoOk { echo: Val }
+);
+request!(
+    /// [GSet::Add] request
+    GSetAdd => GSetAddOk,
+    GSet::Add { element: Val } => AddOk {}
+);
+request!(
+    /// [GSet::Read] request
+    GSetRead => GSetReadOk,
+    GSet::Read {} => ReadOk { value: Vec<Val> }
+);
+request!(
+    /// [Kafka::Send] request
+    KafkaSend => KafkaSendOk,
+    Kafka::Send { key: LogKey, msg: Val } => SendOk { offset: Offset }
+);
+request!(
+    /// [Kafka::Poll] request
+    KafkaPoll => KafkaPollOk,
+    Kafka::Poll { offsets: HashMap<LogKey, Offset> } => PollOk {
+        msgs: HashMap<LogKey, Vec<LogRecord>>,
+    }
+);
+request!(
+    /// [Kafka::CommitOffsets] request
+    KafkaCommitOffsets => KafkaCommitOffsetsOk,
+    Kafka::CommitOffsets { offsets: HashMap<LogKey, Offset> } => CommitOffsetsOk {}
+);
+request!(
+    /// [Kafka::ListCommittedOffsets] request
+    KafkaListCommittedOffsets => KafkaListCommittedOffsetsOk,
+    Kafka::ListCommittedOffsets { keys: Vec<LogKey> } => ListCommittedOffsetsOk {
+        offsets: HashMap<LogKey, Offset>,
+    }
+);
+request!(
+    /// [LinKv::Cas] request, also for the [SeqKv](crate::msg::SeqKv) and
+    /// [LwwKv](crate::msg::LwwKv) services
+    LinKvCas => LinKvCasOk,
+    LinKv::Cas {
+        key: Key,
+        from: Val,
+        to: Val,
+        create_if_not_exists: Option<bool>,
+    } => CasOk {}
+);
+request!(
+    /// [LinKv::Read] request, also for the [SeqKv](crate::msg::SeqKv) and
+    /// [LwwKv](crate::msg::LwwKv) services
+    LinKvRead => LinKvReadOk,
+    LinKv::Read { key: Key } => ReadOk { value: Val }
+);
+request!(
+    /// [LinKv::Write] request, also for the [SeqKv](crate::msg::SeqKv) and
+    /// [LwwKv](crate::msg::LwwKv) services
+    LinKvWrite => LinKvWriteOk,
+    LinKv::Write { key: Key, value: Val } => WriteOk {}
+);
+request!(
+    /// [TxnRwRegister::Txn] request
+    TxnRwRegisterTxn => TxnRwRegisterTxnOk,
+    TxnRwRegister::Txn { txn: Vec<RwRegisterOp> } => TxnOk { txn: Vec<RwRegisterOp> }
+);
+request!(
+    /// [GCounter::Add] request
+    GCounterAdd => GCounterAddOk,
+    GCounter::Add { delta: u64 } => AddOk {}
+);
+request!(
+    /// [GCounter::Read] request
+    GCounterRead => GCounterReadOk,
+    GCounter::Read {} => ReadOk { value: u64 }
+);
+request!(
+    /// [PnCounter::Add] request
+    PnCounterAdd => PnCounterAddOk,
+    PnCounter::Add { delta: i64 } => AddOk {}
+);
+request!(
+    /// [PnCounter::Read] request
+    PnCounterRead => PnCounterReadOk,
+    PnCounter::Read {} => ReadOk { value: i64 }
+);
+request!(
+    /// [Broadcast::Broadcast] request
+    BroadcastRequest => BroadcastResponse,
+    Broadcast::Broadcast { message: Val } => BroadcastOk {}
+);
+request!(
+    /// [Broadcast::Read] request
+    BroadcastRead => BroadcastReadOk,
+    Broadcast::Read {} => ReadOk { messages: Vec<Val> }
+);
+request!(
+    /// [Broadcast::Topology] request
+    BroadcastTopology => BroadcastTopologyOk,
+    Broadcast::Topology { topology: HashMap<Id, Vec<Id>> } => TopologyOk {}
+);
+request!(
+    /// [TxnListAppend::Txn] request
+    TxnListAppendTxn => TxnListAppendTxnOk,
+    TxnListAppend::Txn { txn: Vec<ListAppendOp> } => TxnOk { txn: Vec<ListAppendOp> }
+);
+request!(
+    /// [UniqueIds::Generate] request
+    UniqueIdsGenerate => UniqueIdsGenerateOk,
+    UniqueIds::Generate {} => GenerateOk { id: Val }
+);
+
+#[test]
+fn request_response_pairing() {
+    let body = KafkaSend {
+        key: "k1".to_string(),
+        msg: json!(7),
+    }
+    .into_body(3);
+    assert_eq!(
+        body,
+        Kafka::Send {
+            msg_id: 3,
+            key: "k1".to_string(),
+            msg: json!(7),
+        }
+    );
+    let reply = Kafka::SendOk {
+        in_reply_to: 3,
+        msg_id: None,
+        offset: 12,
+    };
+    assert_eq!(
+        KafkaSend::response(reply),
+        Ok(KafkaSendOk {
+            in_reply_to: 3,
+            msg_id: None,
+            offset: 12,
+        })
+    );
+    // The reply to another request is not the response
+    let reply = Kafka::CommitOffsetsOk {
+        in_reply_to: 3,
+        msg_id: None,
+    };
+    assert!(KafkaSend::response(reply).is_err());
+}
@@ -1,43 +1,112 @@
 //! Node runtime for [Process]es and [Maelstrom networking](https://github.com/jepsen-io/maelstrom/blob/main/doc/protocol.md#protocol)
 
-#[cfg(test)]
+use std::marker::PhantomData;
+use std::pin::pin;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::Arc;
+#[cfg(test)]
+use std::time::Duration;
 
-use async_std::channel::{bounded, Receiver, Sender};
-use async_std::io::stdin;
-use async_std::io::stdout;
-use async_std::io::WriteExt;
+use async_channel::{bounded, unbounded, Receiver, Sender, TrySendError};
 use async_trait::async_trait;
-use log::info;
+use futures::future::{join, join3, select, Either};
+use futures::lock::Mutex;
+use log::{info, log, warn, Level};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize as _, Serialize};
 #[cfg(test)]
+use serde_json::json;
 use serde_json::Value;
 #[cfg(test)]
 use tokio::spawn;
 #[cfg(test)]
 use tokio::test;
 
+use crate::msg;
 use crate::msg::Body;
 #[cfg(test)]
 use crate::msg::Body::Workload;
 use crate::msg::Echo;
 use crate::msg::Init;
-use crate::msg::{Msg, MsgId};
-use crate::process::{ProcNet, Process};
 #[cfg(test)]
-use crate::Error::TestIO;
-use crate::Error::{Deserialize, UnexpectedMsg};
+use crate::msg::MsgId;
+use crate::msg::{body_types, Msg, MsgIdGenerator};
+use crate::process::{Calls, Event, ProcNet, Process};
+use crate::timer::Timers;
 #[cfg(test)]
-use crate::Id;
+use crate::transport::ChannelTransport;
+use crate::transport::{StdioTransport, Transport};
+use crate::Error;
+use crate::Error::{Deserialize, Shutdown, UnexpectedMsg};
 use crate::Result;
 use crate::Status;
+use crate::{ErrorCode, Id};
+
+/// Depth of a runtime queue
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum QueueDepth {
+    /// A queue holding at most the given number of messages, and at least one
+    Bounded(usize),
+    /// A queue without a limit
+    Unbounded,
+}
+
+impl Default for QueueDepth {
+    fn default() -> Self {
+        QueueDepth::Bounded(16)
+    }
+}
 
-const QUEUE_DEPTH: usize = 16;
+impl QueueDepth {
+    fn queue<T>(&self) -> (Sender<T>, Receiver<T>) {
+        match self {
+            QueueDepth::Bounded(depth) => bounded((*depth).max(1)),
+            QueueDepth::Unbounded => unbounded(),
+        }
+    }
+}
+
+/// How IO ingress handles a received message when the process' receive queue is full
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OverflowPolicy {
+    /// Wait for the process to make room, and stop reading input meanwhile
+    ///
+    /// While the process awaits replies to its calls input is still read, so the replies reach
+    /// it. Other messages received meanwhile are held back, in order, until the process makes
+    /// room. The held messages are not limited by the [QueueDepth], so the queue depth does not
+    /// bound memory use while calls are outstanding.
+    #[default]
+    Block,
+    /// Drop the oldest queued event to make room
+    DropOldest,
+    /// Drop the received message, replying with an [ErrorCode::TemporarilyUnavailable] error if
+    /// the sender expects a reply
+    Reject,
+}
+
+/// How IO ingress handles messages it cannot parse
+///
+/// Unparsable messages are logged and skipped. If the sender expects a reply, i.e. the message has
+/// a `src` and a `body.msg_id` and is not itself a reply, the runtime replies with an
+/// [ErrorCode::NotSupported] error if the body's `type` is not a known message type, and an
+/// [ErrorCode::MalformedRequest] error otherwise, e.g. when a message of a known type lacks a
+/// field. The types of untagged and
+/// struct-shaped bodies cannot be known, so any type is considered known if such a body is used.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ParseFailurePolicy {
+    /// No parse failure is fatal
+    #[default]
+    Skip,
+    /// The first parse failure is fatal
+    Fatal,
+    /// A parse failure is fatal once more than the given number of messages failed to parse
+    FatalAfter(usize),
+}
 
 /// Node runtime
 ///
-/// A runtime will create, initialize and run an instance of `P`.
+/// A runtime will initialize and run an instance of `P`.
 ///
 /// Parameters
 /// - `W` the workload body type, e.g. [Echo]
@@ -47,12 +116,42 @@ where
     W: DeserializeOwned + Serialize,
     A: DeserializeOwned + Serialize,
 {
-    line_io: Box<dyn LineIO + Send + Sync>,
-    process: P,
+    transport: Box<dyn Transport + Send + Sync>,
+    /// This node's ID
+    id: Id,
+    process: Mutex<P>,
+    /// The process' initialization, until [Self::init_process] is called
+    init: std::sync::Mutex<Option<PendingInit<W, A>>>,
     /// The process` receive queue
-    process_rxq: Sender<Msg<W, A>>,
+    process_rxq: Sender<Event<W, A>>,
+    /// The receiving end of the process` receive queue, for dropping the oldest event
+    process_rxq_oldest: Receiver<Event<W, A>>,
+    overflow_policy: OverflowPolicy,
+    /// Messages held back from the process' full receive queue, see [OverflowPolicy::Block]
+    held_txq: Sender<Event<W, A>>,
+    held_rxq: Receiver<Event<W, A>>,
+    /// The number of held messages not yet delivered to the process
+    undelivered: AtomicUsize,
+    /// Wakes IO ingress when a held message was delivered, or delivery stopped
+    delivered_txq: Sender<()>,
+    delivered_rxq: Receiver<()>,
     /// The process` transmit queue
     process_txq: Receiver<Msg<W, A>>,
+    /// The process` RPC calls awaiting a reply
+    calls: Arc<Calls<W, A>>,
+    /// Has [Self::shutdown] been called?
+    shutdown: AtomicBool,
+    /// Egress queue for messages sent by the runtime
+    egress_txq: Sender<Msg<W, A>>,
+    parse_failure_policy: ParseFailurePolicy,
+    /// The number of messages ingress failed to parse
+    parse_failures: AtomicUsize,
+    /// Message IDs shared with the process
+    msg_ids: Arc<MsgIdGenerator>,
+    /// Should egress add a `msg_id` to bodies without one?
+    stamp_msg_ids: bool,
+    /// The process` timers
+    timers: Arc<Timers>,
 }
 
 impl<W, A, P: Process<W, A>> Runtime<W, A, P>
@@ -60,53 +159,145 @@ where
     W: DeserializeOwned + Serialize,
     A: DeserializeOwned + Serialize,
 {
-    // Create a new runtime
-    pub async fn new(args: Vec<String>, process: P) -> Result<Self> {
-        Self::new_with_line_io(args, process, Box::new(StdLineIO {})).await
+    /// Create a builder for a runtime running `process`
+    ///
+    /// `args` are passed to the process on initialization.
+    pub fn builder(args: Vec<String>, process: P) -> RuntimeBuilder<W, A, P> {
+        RuntimeBuilder::new(args, process)
     }
 
-    // Create a new runtime for testing
-    #[cfg(test)]
-    async fn new_for_test(
-        args: Vec<String>,
-        process: P,
-        rxq: Receiver<String>,
-        txq: Sender<String>,
-    ) -> Result<Self> {
-        Self::new_with_line_io(args, process, Box::new(QLineIO { rxq, txq })).await
+    /// Run the node
+    ///
+    /// Drive IO egress, IO ingress, the process and its timers concurrently, and return once they
+    /// have completed. At the end of input the runtime shuts down in order: ingress stops, the
+    /// process completes, and egress sends all pending messages. When any one of them fails
+    /// the runtime is shutdown immediately.
+    /// Ingress may be blocked reading input that never arrives, so it is abandoned once egress
+    /// and the process have completed.
+    ///
+    /// A failed process initialization is reported to Maelstrom before the runtime shuts down.
+    ///
+    /// Return
+    /// - [Ok] IFF all completed successfully or due to shutdown,
+    /// - [Err]:[Error::Ingress], [Error::Egress], [Error::Initialization] or [Error::Process] for
+    ///   the part that failed, checked in that order
+    pub async fn run(&self) -> Status {
+        let ingress = async {
+            let status = self.run_io_ingress().await;
+            if status.is_err() {
+                self.shutdown();
+            }
+            status
+        };
+        let egress = async {
+            let status = self.run_io_egress().await;
+            self.shutdown();
+            status
+        };
+        let process = async {
+            let status = match self.run_process().await {
+                Err(Shutdown) => Ok(()),
+                status => status,
+            };
+            // Egress shuts down once it has sent the reply to a failed initialization
+            if status.is_err() && !matches!(status, Err(Error::Initialization(_))) {
+                self.shutdown();
+            }
+            status
+        };
+        let rest = join3(egress, process, self.run_timers());
+        let (ingress, (egress, process, _)) = match select(pin!(ingress), pin!(rest)).await {
+            Either::Left((ingress, rest)) => (ingress, rest.await),
+            Either::Right((rest, _)) => (Ok(()), rest),
+        };
+        ingress.map_err(|e| Error::Ingress(Box::new(e)))?;
+        egress.map_err(|e| Error::Egress(Box::new(e)))?;
+        process.map_err(|e| match e {
+            Error::Initialization(_) => e,
+            e => Error::Process(Box::new(e)),
+        })
     }
 
-    async fn new_with_line_io(
-        args: Vec<String>,
-        mut process: P,
-        line_io: Box<dyn LineIO + Send + Sync>,
-    ) -> Result<Self> {
-        let msg_id = 0;
-        let (id, ids, start_msg_id) = Self::get_init(&*line_io, msg_id).await?;
-        let (process_rxq, rxq) = bounded(QUEUE_DEPTH);
-        let (txq, process_txq) = bounded(QUEUE_DEPTH);
-        let process_net = ProcNet { txq, rxq };
-        process.init(args, process_net, id, ids, start_msg_id);
-        Ok(Self {
-            line_io,
-            process,
-            process_rxq,
-            process_txq,
+    /// Initialize the process, and respond to the node's initialization message
+    ///
+    /// The process may call other nodes while initializing, so IO ingress and egress should run
+    /// concurrently. `init_ok`, or an [ErrorCode::Crash] error if initialization fails, is sent
+    /// via egress. [Self::run_process] initializes the process, unless this was already called.
+    ///
+    /// Return
+    /// - [Ok] IFF the process was initialized, or this was already called,
+    /// - [Err]:[Error::Initialization] IFF the process failed to initialize,
+    /// - [Err] otherwise
+    pub async fn init_process(&self) -> Status {
+        let mut process = self.process.lock().await;
+        let pending = self.init.lock().expect("init lock").take();
+        let PendingInit {
+            msg,
+            args,
+            net,
+            ids,
+        } = match pending {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let status = process.init(args, net, self.id.clone(), ids).await;
+        let rsp = match &status {
+            Ok(()) => msg.reply(|in_reply_to| {
+                Body::Init(Init::InitOk {
+                    in_reply_to,
+                    msg_id: self.msg_ids.next_msg_id(),
+                })
+            })?,
+            Err(e) => msg.reply_error(ErrorCode::Crash, format!("initialization failed: {}", e))?,
+        };
+        self.egress_txq.send(Self::init_msg(rsp)?).await?;
+        status.map_err(|e| match e {
+            Error::Initialization(_) => e,
+            e => Error::Initialization(Box::new(e)),
         })
     }
 
     /// Run the process
     ///
-    /// Run the runtime`s node process. The call will return
+    /// Initialize, see [Self::init_process], and run the runtime`s node process. The call will
+    /// return
     /// - on encountering a fatal error, or
-    /// - after [Self::shutdown] is called
+    /// - after [Self::shutdown] is called, or
+    /// - after the end of input, once the process has handled all received messages
+    ///
+    /// Egress will stop once it has sent all the messages the process sent, and timers stop.
     pub async fn run_process(&self) -> Status {
-        self.process.run().await
+        let status = match self.init_process().await {
+            Ok(()) => self.process.lock().await.run().await,
+            status => status,
+        };
+        self.process_txq.close();
+        self.timers.close();
+        status
+    }
+
+    /// Fire the process' timers until the process completes, the end of input, or
+    /// [Self::shutdown] is called
+    ///
+    /// Timers scheduled via [ProcNet] only fire while this runs.
+    pub async fn run_timers(&self) {
+        self.timers.run(&self.process_rxq).await
     }
 
     /// Run IO egress until [Self::shutdown] is called
-    pub async fn run_io_egress(&self) {
-        while let Ok(_) = self.run_one_io_egress().await {}
+    ///
+    /// Return
+    /// - [Ok] IFF the runtime has shutdown,
+    /// - [Err] otherwise
+    pub async fn run_io_egress(&self) -> Status {
+        loop {
+            match self.run_one_io_egress().await {
+                Ok(()) => {}
+                Err(Shutdown) => return Ok(()),
+                Err(_) if self.is_shutdown() => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     async fn run_one_io_egress(&self) -> Status {
@@ -114,130 +305,481 @@ where
         Ok(())
     }
 
-    /// Run IO ingress until [Self::shutdown] is called
-    pub async fn run_io_ingress(&self) {
-        while let Ok(_) = self.run_one_io_ingress().await {}
+    /// Run IO ingress until the end of input, or [Self::shutdown] is called
+    ///
+    /// At the end of input the process' receive queue is closed, so the process can complete
+    /// after handling all received messages, and outstanding RPC calls fail.
+    ///
+    /// Return
+    /// - [Ok] IFF the input ended or the runtime has shutdown,
+    /// - [Err] otherwise
+    pub async fn run_io_ingress(&self) -> Status {
+        let read = async {
+            let status = self.run_io_reads().await;
+            // Delivery completes once the held messages are delivered
+            self.held_txq.close();
+            status
+        };
+        let (status, ()) = join(read, self.run_io_delivery()).await;
+        status
+    }
+
+    /// Read messages, routing replies to calls and delivering others to the process
+    async fn run_io_reads(&self) -> Status {
+        loop {
+            match self.run_one_io_ingress().await {
+                Ok(true) => {}
+                Ok(false) => {
+                    info!("end of input");
+                    self.calls.close();
+                    self.timers.close();
+                    return Ok(());
+                }
+                Err(Shutdown) => return Ok(()),
+                Err(_) if self.is_shutdown() => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Deliver the held messages to the process in order, waiting for room in its receive queue
+    ///
+    /// Once no more messages are held the process' receive queue is closed.
+    async fn run_io_delivery(&self) {
+        while let Ok(event) = self.held_rxq.recv().await {
+            let delivered = self.process_rxq.send(event).await;
+            self.undelivered.fetch_sub(1, SeqCst);
+            let _ = self.delivered_txq.try_send(());
+            if delivered.is_err() {
+                break;
+            }
+        }
+        self.held_rxq.close();
+        let _ = self.delivered_txq.try_send(());
+        self.process_rxq.close();
+    }
+
+    /// Wait until the held messages are delivered, unless the process awaits call replies
+    async fn wait_for_delivery(&self) {
+        while self.undelivered.load(SeqCst) > 0
+            && !self.calls.is_waiting()
+            && !self.held_txq.is_closed()
+        {
+            select(
+                pin!(self.delivered_rxq.recv()),
+                pin!(self.calls.registered()),
+            )
+            .await;
+        }
+    }
+
+    /// Receive and deliver one message
+    ///
+    /// Return `false` IFF the input has ended
+    async fn run_one_io_ingress(&self) -> Result<bool> {
+        self.wait_for_delivery().await;
+        let line = match self.transport.read_line().await? {
+            Some(line) => line,
+            None => return Ok(false),
+        };
+        let value = match serde_json::from_str::<Value>(&line) {
+            Ok(value) => value,
+            Err(e) => {
+                self.reject(&line, &Value::Null, e).await?;
+                return Ok(true);
+            }
+        };
+        let msg = match Msg::<W, A>::deserialize(&value) {
+            Ok(msg) => msg,
+            Err(e) => {
+                self.reject(&line, &value, e).await?;
+                return Ok(true);
+            }
+        };
+        let in_reply_to = value["body"]["in_reply_to"].as_u64();
+        if let Some(msg) = self.calls.route(msg, in_reply_to) {
+            self.deliver(msg, &value).await?;
+        }
+        Ok(true)
+    }
+
+    /// Deliver a received message to the process, according to the [OverflowPolicy]
+    async fn deliver(&self, msg: Msg<W, A>, value: &Value) -> Status {
+        let mut event = Event::Msg(msg);
+        loop {
+            // Held messages are delivered first
+            if self.undelivered.load(SeqCst) == 0 {
+                event = match self.process_rxq.try_send(event) {
+                    Ok(()) => return Ok(()),
+                    Err(TrySendError::Closed(_)) => return Err(Shutdown),
+                    Err(TrySendError::Full(event)) => event,
+                };
+            }
+            match self.overflow_policy {
+                OverflowPolicy::Block => {
+                    // Rather than wait for room here, so replies to calls can still be read
+                    self.undelivered.fetch_add(1, SeqCst);
+                    return self.held_txq.send(event).await.map_err(|_| Shutdown);
+                }
+                OverflowPolicy::DropOldest => {
+                    if self.process_rxq_oldest.try_recv().is_ok() {
+                        warn!("process receive queue full, dropped the oldest event");
+                    }
+                }
+                OverflowPolicy::Reject => {
+                    warn!("process receive queue full, rejected message {}", value);
+                    let text = "process receive queue full".to_string();
+                    return self
+                        .reply_error(value, ErrorCode::TemporarilyUnavailable, text)
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// Reject a message that could not be parsed
+    ///
+    /// Report the failure, and reply with an error if the sender expects a reply.
+    ///
+    /// Return [Err] IFF the failure is fatal according to the [ParseFailurePolicy]
+    async fn reject(&self, line: &str, value: &Value, e: serde_json::Error) -> Status {
+        warn!("rejected message {}: {}", line, e);
+        let (code, text) = match value["body"]["type"].as_str() {
+            Some(t) if Self::is_unsupported_type(t) => {
+                (ErrorCode::NotSupported, format!("unsupported {}: {}", t, e))
+            }
+            _ => (
+                ErrorCode::MalformedRequest,
+                format!("malformed request: {}", e),
+            ),
+        };
+        self.reply_error(value, code, text).await?;
+        let failures = self.parse_failures.fetch_add(1, SeqCst) + 1;
+        match self.parse_failure_policy {
+            ParseFailurePolicy::Fatal => Err(Deserialize(e)),
+            ParseFailurePolicy::FatalAfter(n) if failures > n => Err(Deserialize(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Is `t` definitely not the type of a message body the runtime can deserialize?
+    ///
+    /// A type is unsupported IFF it is not an error, and none of [Init], `W` or `A` may have it.
+    /// Body types whose types cannot be determined, e.g. untagged enums, may have any type.
+    fn is_unsupported_type(t: &str) -> bool {
+        t != "error"
+            && [body_types::<Init>(), body_types::<W>(), body_types::<A>()]
+                .iter()
+                .all(|types| types.excludes(t))
     }
 
-    async fn run_one_io_ingress(&self) -> Status {
-        self.process_rxq.send(self.recv_msg().await?).await?;
+    /// Reply to the received message `value` with an error, if the sender expects a reply
+    ///
+    /// The sender expects a reply IFF the message has a `src` and a `body.msg_id`, and is not a
+    /// reply, i.e. has no `body.in_reply_to`.
+    async fn reply_error(&self, value: &Value, code: ErrorCode, text: String) -> Status {
+        if value["body"].get("in_reply_to").is_some() {
+            return Ok(());
+        }
+        if let (Some(src), Some(msg_id)) = (value["src"].as_str(), value["body"]["msg_id"].as_u64())
+        {
+            let reply = Msg {
+                src: self.id.clone(),
+                dest: src.to_string(),
+                body: Body::Error(msg::Error {
+                    in_reply_to: msg_id,
+                    code,
+                    text,
+                }),
+            };
+            self.egress_txq.send(reply).await?;
+        }
         Ok(())
     }
 
     /// Shutdown the runtime
     pub fn shutdown(&self) {
+        self.shutdown.store(true, SeqCst);
+        self.held_txq.close();
         self.process_rxq.close();
         self.process_txq.close();
-        self.line_io.close();
+        self.calls.close();
+        self.timers.close();
+        self.transport.close();
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown.load(SeqCst)
     }
 
     /// Get initialization for the node
     ///
-    /// Receives the next message and asserts it is [Init] message, responds if valid.
+    /// Receives the next message and asserts it is [Init] message.
     ///
-    /// Return the node's ID, all the participating node IDs, the next message ID to use.
-    async fn get_init(
-        line_io: &dyn LineIO,
-        start_msg_id: MsgId,
-    ) -> Result<(String, Vec<String>, MsgId)> {
-        let init_data = line_io.read_line().await?;
-        let Msg { src, body, .. }: Msg<Echo, ()> = serde_json::from_str(&init_data)?;
-        match body {
+    /// Return the message, the node's ID, and all the participating node IDs.
+    async fn get_init(transport: &dyn Transport) -> Result<(Msg<Echo, ()>, Id, Vec<Id>)> {
+        let init_data = transport.read_line().await?.ok_or(Shutdown)?;
+        let msg: Msg<Echo, ()> = serde_json::from_str(&init_data)?;
+        let (node_id, node_ids) = match &msg.body {
             Body::Init(Init::Init {
-                msg_id,
-                node_id,
-                node_ids,
-            }) => {
-                let rsp: Msg<Echo, ()> = Msg {
-                    src: node_id.clone(),
-                    dest: src,
-                    body: Body::Init(Init::InitOk {
-                        in_reply_to: msg_id,
-                        msg_id: start_msg_id,
-                    }),
-                };
-                let line = serde_json::to_string(&rsp)?;
-                line_io.write_line(&line).await?;
-                Ok((node_id, node_ids, start_msg_id + 1))
-            }
-            _ => Err(UnexpectedMsg { expected: "Init" }),
-        }
+                node_id, node_ids, ..
+            }) => (node_id.clone(), node_ids.clone()),
+            _ => return Err(UnexpectedMsg { expected: "Init" }),
+        };
+        Ok((msg, node_id, node_ids))
     }
 
-    /// Get the next message
-    async fn recv_msg(&self) -> Result<Msg<W, A>> {
-        let line = self.line_io.read_line().await?;
-        serde_json::from_str::<Msg<W, A>>(&line).map_err(|e| Deserialize(e))
+    /// Convert a message with an initialization or error body to the process' message type
+    ///
+    /// These bodies are the same for all message types.
+    fn init_msg(msg: Msg<Echo, ()>) -> Result<Msg<W, A>> {
+        let Msg { src, dest, body } = msg;
+        let body = match body {
+            Body::Init(init) => Body::Init(init),
+            Body::Error(e) => Body::Error(e),
+            _ => return Err(UnexpectedMsg { expected: "Init" }),
+        };
+        Ok(Msg { src, dest, body })
     }
 
     /// Send a message
     async fn send_msg(&self, msg: &Msg<W, A>) -> Status {
-        let line = serde_json::to_string(&msg)?;
-        self.line_io.write_line(&line).await?;
+        let line = if self.stamp_msg_ids {
+            let mut value = serde_json::to_value(msg).map_err(Error::Serialize)?;
+            if let Some(body) = value["body"].as_object_mut() {
+                if body.get("msg_id").is_none_or(Value::is_null) {
+                    body.insert("msg_id".to_string(), self.msg_ids.next_msg_id().into());
+                }
+            }
+            serde_json::to_string(&value)
+        } else {
+            serde_json::to_string(msg)
+        }
+        .map_err(Error::Serialize)?;
+        self.transport.write_line(&line).await?;
         Ok(())
     }
 }
 
-/// Line IO
+/// [Runtime] builder
 ///
-/// Line IO to send and receive message to and from the Maelstrom OS process.
-/// The trait allows an implementation for testing within the local OS process.
-#[async_trait]
-trait LineIO {
-    async fn read_line(&self) -> Result<String>;
-    async fn write_line(&self, line: &str) -> Status;
-    fn close(&self);
+/// Configure the runtime, then create it with [RuntimeBuilder::build].
+pub struct RuntimeBuilder<W, A, P: Process<W, A>>
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    args: Vec<String>,
+    process: P,
+    transport: Box<dyn Transport + Send + Sync>,
+    traffic_log_level: Option<Level>,
+    ingress_depth: QueueDepth,
+    egress_depth: QueueDepth,
+    overflow_policy: OverflowPolicy,
+    parse_failure_policy: ParseFailurePolicy,
+    stamp_msg_ids: bool,
+    _bodies: PhantomData<fn() -> (W, A)>,
 }
 
-/// Line IO from `stdin` and `stdout`
-struct StdLineIO {}
+impl<W, A, P: Process<W, A>> RuntimeBuilder<W, A, P>
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    /// Create a builder for a runtime running `process`, with default configuration
+    ///
+    /// `args` are passed to the process on initialization.
+    pub fn new(args: Vec<String>, process: P) -> Self {
+        Self {
+            args,
+            process,
+            transport: Box::<StdioTransport>::default(),
+            traffic_log_level: Some(Level::Info),
+            ingress_depth: Default::default(),
+            egress_depth: Default::default(),
+            overflow_policy: Default::default(),
+            parse_failure_policy: Default::default(),
+            stamp_msg_ids: false,
+            _bodies: PhantomData,
+        }
+    }
+
+    /// Set the transport carrying messages to and from the Maelstrom network
+    ///
+    /// The default is [StdioTransport].
+    pub fn transport(mut self, transport: Box<dyn Transport + Send + Sync>) -> Self {
+        self.transport = transport;
+        self
+    }
 
-#[async_trait]
-impl LineIO for StdLineIO {
-    async fn read_line(&self) -> Result<String> {
-        let mut line = String::new();
-        stdin().read_line(&mut line).await?;
-        info!("received: {}", line);
-        Ok(line)
+    /// Set the level at which received and sent lines are logged, or [None] to not log them
+    ///
+    /// The default is [Level::Info].
+    pub fn traffic_log_level(mut self, level: Option<Level>) -> Self {
+        self.traffic_log_level = level;
+        self
     }
 
-    async fn write_line(&self, line: &str) -> Status {
-        stdout().write_all(line.as_bytes()).await?;
-        stdout().write_all("\n".as_bytes()).await?;
-        info!("sent {}", line);
-        Ok(())
+    /// Set the depth of the process' receive queue
+    ///
+    /// When the queue is full IO ingress applies the [OverflowPolicy]. The default is 16. With
+    /// [OverflowPolicy::Block] messages received while the process awaits call replies are held
+    /// beyond this depth.
+    pub fn ingress_depth(mut self, depth: QueueDepth) -> Self {
+        self.ingress_depth = depth;
+        self
     }
 
-    fn close(&self) {
-        // No op. stdin and stdout will close when Maelstrom closes its end.
+    /// Set the depth of the process' transmit queue
+    ///
+    /// When the queue is full sending waits for IO egress to make room. The default is 16.
+    pub fn egress_depth(mut self, depth: QueueDepth) -> Self {
+        self.egress_depth = depth;
+        self
+    }
+
+    /// Set how IO ingress handles a received message when the process' receive queue is full
+    ///
+    /// The default is [OverflowPolicy::Block].
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Set how IO ingress handles messages it cannot parse
+    ///
+    /// The default is [ParseFailurePolicy::Skip].
+    pub fn parse_failure_policy(mut self, policy: ParseFailurePolicy) -> Self {
+        self.parse_failure_policy = policy;
+        self
+    }
+
+    /// Set whether IO egress adds a unique `msg_id` to message bodies without one
+    ///
+    /// IDs are allocated from the generator shared with the process. The default is `false`.
+    pub fn stamp_msg_ids(mut self, stamp: bool) -> Self {
+        self.stamp_msg_ids = stamp;
+        self
+    }
+
+    /// Create the runtime
+    ///
+    /// Receive the node's initialization message. The process is initialized, and the message
+    /// responded to, once the runtime runs, see [Runtime::init_process].
+    ///
+    /// Return
+    /// - [Ok] IFF the initialization message was received,
+    /// - [Err] otherwise
+    pub async fn build(self) -> Result<Runtime<W, A, P>> {
+        let Self {
+            args,
+            process,
+            transport,
+            traffic_log_level,
+            ingress_depth,
+            egress_depth,
+            overflow_policy,
+            parse_failure_policy,
+            stamp_msg_ids,
+            ..
+        } = self;
+        let transport = match traffic_log_level {
+            Some(level) => Box::new(LoggedTransport {
+                inner: transport,
+                level,
+            }),
+            None => transport,
+        };
+        let msg_ids: Arc<MsgIdGenerator> = Default::default();
+        let (init, id, ids) = Runtime::<W, A, P>::get_init(&*transport).await?;
+        let (process_rxq, rxq) = ingress_depth.queue();
+        let (txq, process_txq) = egress_depth.queue();
+        let calls: Arc<Calls<W, A>> = Default::default();
+        let egress_txq = txq.clone();
+        let timers: Arc<Timers> = Default::default();
+        let (held_txq, held_rxq) = unbounded();
+        let (delivered_txq, delivered_rxq) = bounded(1);
+        let process_net = ProcNet::new(
+            id.clone(),
+            txq,
+            rxq.clone(),
+            calls.clone(),
+            msg_ids.clone(),
+            timers.clone(),
+        );
+        Ok(Runtime {
+            transport,
+            id,
+            process: Mutex::new(process),
+            init: std::sync::Mutex::new(Some(PendingInit {
+                msg: init,
+                args,
+                net: process_net,
+                ids,
+            })),
+            process_rxq,
+            process_rxq_oldest: rxq,
+            overflow_policy,
+            held_txq,
+            held_rxq,
+            undelivered: AtomicUsize::new(0),
+            delivered_txq,
+            delivered_rxq,
+            process_txq,
+            calls,
+            shutdown: AtomicBool::new(false),
+            egress_txq,
+            parse_failure_policy,
+            parse_failures: AtomicUsize::new(0),
+            msg_ids,
+            stamp_msg_ids,
+            timers,
+        })
     }
 }
 
-/// LineIO implementation for local OS process testing
-#[cfg(test)]
-struct QLineIO {
-    rxq: Receiver<String>,
-    txq: Sender<String>,
+/// A process initialization, pending until the process runs
+struct PendingInit<W, A>
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+{
+    /// The node's initialization message
+    msg: Msg<Echo, ()>,
+    args: Vec<String>,
+    net: ProcNet<W, A>,
+    ids: Vec<Id>,
+}
+
+/// Transport logging the lines read and written
+struct LoggedTransport {
+    inner: Box<dyn Transport + Send + Sync>,
+    level: Level,
 }
 
 #[async_trait]
-#[cfg(test)]
-impl LineIO for QLineIO {
-    async fn read_line(&self) -> Result<String> {
-        self.rxq.recv().await.map_err(|_| TestIO)
+impl Transport for LoggedTransport {
+    async fn read_line(&self) -> Result<Option<String>> {
+        let line = self.inner.read_line().await?;
+        if let Some(line) = &line {
+            log!(self.level, "received {}", line);
+        }
+        Ok(line)
     }
 
     async fn write_line(&self, line: &str) -> Status {
-        self.txq.send(line.to_string()).await.map_err(|_| TestIO)
+        self.inner.write_line(line).await?;
+        log!(self.level, "sent {}", line);
+        Ok(())
     }
 
     fn close(&self) {
-        self.rxq.close();
-        self.txq.close();
+        self.inner.close()
     }
 }
 
 #[cfg(test)]
+#[derive(Default)]
 struct EchoProcess {
     args: Vec<String>,
     net: ProcNet<Echo, ()>,
@@ -245,44 +787,32 @@ struct EchoProcess {
     ids: Vec<Id>,
 }
 
-#[cfg(test)]
-impl Default for EchoProcess {
-    fn default() -> Self {
-        Self {
-            args: Default::default(),
-            net: Default::default(),
-            id: Default::default(),
-            ids: Default::default(),
-        }
-    }
-}
-
 #[cfg(test)]
 #[async_trait]
 impl Process<Echo, ()> for EchoProcess {
-    fn init(
+    async fn init(
         &mut self,
         args: Vec<String>,
         net: ProcNet<Echo, ()>,
         id: Id,
         ids: Vec<Id>,
-        _start_msg_id: MsgId,
-    ) {
+    ) -> Status {
         self.args = args;
         self.net = net;
         self.id = id;
         self.ids = ids;
+        Ok(())
     }
 
     async fn run(&self) -> Status {
         loop {
             // Respond to all echo messages with an echo_ok message echoing the `echo` field
             match self.net.rxq.recv().await {
-                Ok(Msg {
+                Ok(Event::Msg(Msg {
                     src,
                     body: Workload(Echo::Echo { msg_id, echo }),
                     ..
-                }) => {
+                })) => {
                     self.net
                         .txq
                         .send(Msg {
@@ -328,17 +858,27 @@ async fn test_runtime() {
 
     // Create and drive the runtime
     let r = Arc::new(
-        Runtime::new_for_test(Default::default(), e, erxq, etxq)
+        Runtime::builder(Default::default(), e)
+            .transport(Box::new(ChannelTransport::new(erxq, etxq)))
+            .build()
             .await
             .expect("new runtime"),
     );
 
+    let r1 = r.clone();
+    let r2 = r.clone();
+    let r3 = r.clone();
+    let t1 = spawn(async move { r1.run_io_egress().await });
+    let t2 = spawn(async move { r2.run_io_ingress().await });
+    let t3 = spawn(async move { r3.run_process().await });
+
     // Verify process responds with init_ok
     let init_ok_data: String = rxq.recv().await.expect("recv init_ok");
     if let Msg::<Echo, ()> {
         src,
         dest,
         body: Body::Init(Init::InitOk { in_reply_to, .. }),
+        ..
     } = serde_json::from_str(&init_ok_data).expect("deserialized init_ok")
     {
         assert_eq!(in_reply_to, 0);
@@ -348,13 +888,6 @@ async fn test_runtime() {
         panic!("expected init_ok")
     }
 
-    let r1 = r.clone();
-    let r2 = r.clone();
-    let r3 = r.clone();
-    let t1 = spawn(async move { r1.run_io_egress().await });
-    let t2 = spawn(async move { r2.run_io_ingress().await });
-    let t3 = spawn(async move { r3.run_process().await });
-
     // Send echo requests and receive responses ...
     for msg_id in 0..5 {
         let echo_data = Value::String(format!("boo! {}", msg_id));
@@ -394,3 +927,969 @@ async fn test_runtime() {
     r.shutdown();
     let _ = tokio::join!(t1, t2, t3);
 }
+
+/// Forwards echo requests to node `b` via RPC, and echoes `b`'s response
+#[cfg(test)]
+#[derive(Default)]
+struct ForwardingProcess {
+    net: ProcNet<Echo, ()>,
+    id: Id,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Process<Echo, ()> for ForwardingProcess {
+    async fn init(
+        &mut self,
+        _args: Vec<String>,
+        net: ProcNet<Echo, ()>,
+        id: Id,
+        _ids: Vec<Id>,
+    ) -> Status {
+        self.net = net;
+        self.id = id;
+        Ok(())
+    }
+
+    async fn run(&self) -> Status {
+        while let Ok(event) = self.net.rxq.recv().await {
+            let (src, msg_id, echo) = match event {
+                Event::Msg(Msg {
+                    src,
+                    body: Workload(Echo::Echo { msg_id, echo }),
+                    ..
+                }) => (src, msg_id, echo),
+                _ => panic!("unexpected event {:?}", event),
+            };
+            let request = Workload(Echo::Echo {
+                msg_id: msg_id + 100,
+                echo,
+            });
+            let echo = match self.net.call("b".to_string(), request).await? {
+                Msg {
+                    body: Workload(Echo::EchoOk { echo, .. }),
+                    ..
+                } => echo,
+                reply => panic!("unexpected reply {:?}", reply),
+            };
+            self.net
+                .txq
+                .send(Msg {
+                    src: self.id.clone(),
+                    dest: src,
+                    body: Workload(Echo::EchoOk {
+                        in_reply_to: msg_id,
+                        msg_id: None,
+                        echo,
+                    }),
+                })
+                .await?;
+        }
+        Ok(()) // Runtime is shutting down.
+    }
+}
+
+/// Create a runtime for `process`, and complete node initialization
+///
+/// Return the runtime, the runtime's input queue and the runtime's output queue
+#[cfg(test)]
+async fn new_initialized_for_test<W, A, P>(
+    process: P,
+) -> (Arc<Runtime<W, A, P>>, Sender<String>, Receiver<String>)
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+    P: Process<W, A>,
+{
+    new_configured_for_test(process, |builder| builder).await
+}
+
+/// Create a runtime for `process` as configured by `configure`, with an init message waiting
+///
+/// Return the runtime, the runtime's input queue and the runtime's output queue
+#[cfg(test)]
+pub(crate) async fn new_uninitialized_for_test<W, A, P>(
+    process: P,
+    configure: impl FnOnce(RuntimeBuilder<W, A, P>) -> RuntimeBuilder<W, A, P>,
+) -> (Runtime<W, A, P>, Sender<String>, Receiver<String>)
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+    P: Process<W, A>,
+{
+    let (txq, erxq) = bounded(10);
+    let (etxq, rxq) = bounded(10);
+    let init = Msg::<Echo, ()> {
+        src: "test".to_string(),
+        dest: "a".to_string(),
+        body: Body::Init(Init::Init {
+            msg_id: 0,
+            node_id: "a".to_string(),
+            node_ids: vec!["a".to_string(), "b".to_string()],
+        }),
+    };
+    txq.send(serde_json::to_string(&init).expect("serialize init"))
+        .await
+        .expect("send message");
+    let builder = Runtime::builder(Default::default(), process)
+        .transport(Box::new(ChannelTransport::new(erxq, etxq)));
+    let r = configure(builder).build().await.expect("new runtime");
+    (r, txq, rxq)
+}
+
+/// Create a runtime for `process` as configured by `configure`, and complete node initialization
+///
+/// Return the runtime, the runtime's input queue and the runtime's output queue
+#[cfg(test)]
+async fn new_configured_for_test<W, A, P>(
+    process: P,
+    configure: impl FnOnce(RuntimeBuilder<W, A, P>) -> RuntimeBuilder<W, A, P>,
+) -> (Arc<Runtime<W, A, P>>, Sender<String>, Receiver<String>)
+where
+    W: DeserializeOwned + Serialize,
+    A: DeserializeOwned + Serialize,
+    P: Process<W, A>,
+{
+    let (r, txq, rxq) = new_uninitialized_for_test(process, configure).await;
+    // Initialize, and pass init_ok on to the test
+    let (init, egress) = futures::join!(r.init_process(), r.run_one_io_egress());
+    init.expect("initialized");
+    egress.expect("sent init_ok");
+    let init_ok: Msg<Echo, ()> =
+        serde_json::from_str(&rxq.recv().await.expect("recv init_ok")).expect("init_ok");
+    assert!(matches!(init_ok.body, Body::Init(Init::InitOk { .. })));
+    (Arc::new(r), txq, rxq)
+}
+
+#[test]
+async fn test_runtime_rpc() {
+    let (r, txq, rxq) = new_initialized_for_test(ForwardingProcess::default()).await;
+    let (r1, r2, r3) = (r.clone(), r.clone(), r.clone());
+    let t1 = spawn(async move { r1.run_io_egress().await });
+    let t2 = spawn(async move { r2.run_io_ingress().await });
+    let t3 = spawn(async move { r3.run_process().await });
+
+    for msg_id in 0..3 {
+        let echo = Msg::<Echo, ()> {
+            src: "test".to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::Echo {
+                msg_id,
+                echo: Value::String(format!("boo! {}", msg_id)),
+            }),
+        };
+        txq.send(serde_json::to_string(&echo).expect("serialized"))
+            .await
+            .expect("sent echo request");
+
+        // Respond to the forwarded request as node `b`
+        let forwarded: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("forwarded")).expect("deserialized");
+        let (forwarded_msg_id, echo) = match forwarded {
+            Msg {
+                src,
+                dest,
+                body: Workload(Echo::Echo { msg_id, echo }),
+                ..
+            } => {
+                assert_eq!(src, "a");
+                assert_eq!(dest, "b");
+                (msg_id, echo)
+            }
+            msg => panic!("expected forwarded echo, got {:?}", msg),
+        };
+        assert_eq!(forwarded_msg_id, msg_id + 100);
+        let reply = Msg::<Echo, ()> {
+            src: "b".to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::EchoOk {
+                in_reply_to: forwarded_msg_id,
+                msg_id: None,
+                echo: Value::String(format!("{} from b", echo.as_str().expect("string"))),
+            }),
+        };
+        txq.send(serde_json::to_string(&reply).expect("serialized"))
+            .await
+            .expect("sent echo reply");
+
+        // The reply is routed to the call, rather than the process' receive queue
+        let echoed: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+        match echoed {
+            Msg {
+                dest,
+                body:
+                    Workload(Echo::EchoOk {
+                        in_reply_to, echo, ..
+                    }),
+                ..
+            } => {
+                assert_eq!(dest, "test");
+                assert_eq!(in_reply_to, msg_id);
+                assert_eq!(echo, Value::String(format!("boo! {} from b", msg_id)));
+            }
+            msg => panic!("expected echo_ok, got {:?}", msg),
+        }
+    }
+
+    r.shutdown();
+    let _ = tokio::join!(t1, t2, t3);
+}
+
+/// Fails on initialization with `init_failure` if set, and on receiving any message otherwise
+#[cfg(test)]
+#[derive(Default)]
+struct FailingProcess {
+    init_failure: Option<Error>,
+    net: ProcNet<Echo, ()>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Process<Echo, ()> for FailingProcess {
+    async fn init(
+        &mut self,
+        _args: Vec<String>,
+        net: ProcNet<Echo, ()>,
+        _id: Id,
+        _ids: Vec<Id>,
+    ) -> Status {
+        if let Some(e) = self.init_failure.take() {
+            return Err(e);
+        }
+        self.net = net;
+        Ok(())
+    }
+
+    async fn run(&self) -> Status {
+        self.net.rxq.recv().await?;
+        Err(UnexpectedMsg {
+            expected: "nothing",
+        })
+    }
+}
+
+#[test]
+async fn test_runtime_run() {
+    let (r, txq, rxq) = new_initialized_for_test(EchoProcess::default()).await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    let echo = Msg::<Echo, ()> {
+        src: "test".to_string(),
+        dest: "a".to_string(),
+        body: Workload(Echo::Echo {
+            msg_id: 1,
+            echo: Value::String("boo!".to_string()),
+        }),
+    };
+    txq.send(serde_json::to_string(&echo).expect("serialized"))
+        .await
+        .expect("sent echo request");
+    let echoed: Msg<Echo, ()> =
+        serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+    assert!(matches!(
+        echoed.body,
+        Workload(Echo::EchoOk { in_reply_to: 1, .. })
+    ));
+
+    r.shutdown();
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+#[test]
+async fn test_runtime_run_process_failure() {
+    let (r, txq, _rxq) = new_initialized_for_test(FailingProcess::default()).await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    let echo = Msg::<Echo, ()> {
+        src: "test".to_string(),
+        dest: "a".to_string(),
+        body: Workload(Echo::Echo {
+            msg_id: 1,
+            echo: Value::String("boo!".to_string()),
+        }),
+    };
+    txq.send(serde_json::to_string(&echo).expect("serialized"))
+        .await
+        .expect("sent echo request");
+
+    // The process fails, and the runtime stops ingress and egress
+    let status = t.await.expect("joined");
+    assert!(
+        matches!(&status, Err(Error::Process(e)) if matches!(**e, UnexpectedMsg { .. })),
+        "{:?}",
+        status
+    );
+}
+
+#[test]
+async fn test_runtime_init_failure() {
+    let process = FailingProcess {
+        init_failure: Some(Error::TestIO),
+        ..Default::default()
+    };
+    let (r, _txq, rxq) = new_uninitialized_for_test(process, |builder| builder).await;
+    let status = r.run().await;
+    assert!(
+        matches!(&status, Err(Error::Initialization(e)) if matches!(**e, Error::TestIO)),
+        "{:?}",
+        status
+    );
+
+    // The init is answered with an error rather than init_ok
+    let rsp: Msg<Echo, ()> =
+        serde_json::from_str(&rxq.recv().await.expect("recv init reply")).expect("deserialized");
+    assert_eq!(rsp.src, "a");
+    assert_eq!(rsp.dest, "test");
+    assert!(
+        matches!(
+            &rsp.body,
+            Body::Error(msg::Error {
+                in_reply_to: 0,
+                code: ErrorCode::Crash,
+                ..
+            })
+        ),
+        "{:?}",
+        rsp
+    );
+}
+
+#[test]
+async fn test_runtime_init_failure_not_wrapped_twice() {
+    let process = FailingProcess {
+        init_failure: Some(Error::Initialization(Box::new(Error::TestIO))),
+        ..Default::default()
+    };
+    let (r, _txq, _rxq) = new_uninitialized_for_test(process, |builder| builder).await;
+    let status = r.run().await;
+    assert!(
+        matches!(&status, Err(Error::Initialization(e)) if matches!(**e, Error::TestIO)),
+        "{:?}",
+        status
+    );
+}
+
+/// Reads a value from the lin-kv service on initialization, and sends it to `test` when run
+#[cfg(test)]
+#[derive(Default)]
+struct LinKvInitProcess {
+    net: ProcNet<Echo, msg::LinKv>,
+    value: Option<Value>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Process<Echo, msg::LinKv> for LinKvInitProcess {
+    async fn init(
+        &mut self,
+        _args: Vec<String>,
+        net: ProcNet<Echo, msg::LinKv>,
+        _id: Id,
+        _ids: Vec<Id>,
+    ) -> Status {
+        let read = crate::rpc::LinKvRead { key: json!("k") };
+        let crate::rpc::LinKvReadOk { value, .. } =
+            net.request(msg::LIN_KV.to_string(), read).await?;
+        self.value = Some(value);
+        self.net = net;
+        Ok(())
+    }
+
+    async fn run(&self) -> Status {
+        let body = Workload(Echo::Echo {
+            msg_id: self.net.next_msg_id(),
+            echo: self.value.clone().expect("initialized"),
+        });
+        self.net
+            .txq
+            .send(Msg {
+                src: "a".to_string(),
+                dest: "test".to_string(),
+                body,
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// Run a [LinKvInitProcess], with `flood` messages from a peer arriving during its init call
+#[cfg(test)]
+async fn init_call_for_test(ingress_depth: QueueDepth, flood: MsgId) {
+    let (r, txq, rxq) = new_uninitialized_for_test(LinKvInitProcess::default(), |builder| {
+        builder.ingress_depth(ingress_depth)
+    })
+    .await;
+
+    let run = r.run();
+    let check = async {
+        // The process reads from lin-kv before the node responds to init
+        let read: Msg<Echo, msg::LinKv> =
+            serde_json::from_str(&rxq.recv().await.expect("read")).expect("deserialized");
+        assert_eq!(read.dest, msg::LIN_KV);
+        let msg_id = match read.body {
+            Body::Application(msg::LinKv::Read { msg_id, .. }) => msg_id,
+            body => panic!("expected read, got {:?}", body),
+        };
+        for msg_id in 0..flood {
+            let peer = Msg::<Echo, msg::LinKv> {
+                src: "b".to_string(),
+                dest: "a".to_string(),
+                body: Workload(Echo::Echo {
+                    msg_id,
+                    echo: json!("flood"),
+                }),
+            };
+            txq.send(serde_json::to_string(&peer).expect("serialized"))
+                .await
+                .expect("sent flood");
+        }
+        // Messages beyond the process' receive queue are held while the call is outstanding
+        let held = match ingress_depth {
+            QueueDepth::Bounded(depth) => flood.saturating_sub(depth.max(1) as MsgId),
+            QueueDepth::Unbounded => 0,
+        };
+        while (r.undelivered.load(SeqCst) as MsgId) < held {
+            crate::backend::sleep(Duration::from_millis(1)).await;
+        }
+        let read_ok = Msg::<Echo, msg::LinKv> {
+            src: msg::LIN_KV.to_string(),
+            dest: "a".to_string(),
+            body: Body::Application(msg::LinKv::ReadOk {
+                in_reply_to: msg_id,
+                msg_id: None,
+                value: json!(42),
+            }),
+        };
+        txq.send(serde_json::to_string(&read_ok).expect("serialized"))
+            .await
+            .expect("sent read_ok");
+
+        let init_ok: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("init_ok")).expect("deserialized");
+        assert!(
+            matches!(
+                init_ok.body,
+                Body::Init(Init::InitOk { in_reply_to: 0, .. })
+            ),
+            "{:?}",
+            init_ok
+        );
+        let echo: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("echo")).expect("deserialized");
+        assert!(
+            matches!(&echo.body, Workload(Echo::Echo { echo, .. }) if echo == &json!(42)),
+            "{:?}",
+            echo
+        );
+        txq.close();
+    };
+    let (status, _) = futures::join!(run, check);
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+#[test]
+async fn test_runtime_init_calls_service() {
+    init_call_for_test(Default::default(), 0).await;
+}
+
+#[test]
+async fn test_runtime_init_calls_service_under_back_pressure() {
+    // The reply follows more messages than the process' receive queue holds
+    init_call_for_test(QueueDepth::Bounded(1), 5).await;
+}
+
+#[test]
+async fn test_runtime_holds_messages_beyond_depth_during_call() {
+    // Held messages are not limited by the depth of the process' receive queue
+    init_call_for_test(QueueDepth::Bounded(2), 40).await;
+}
+
+#[test]
+async fn test_runtime_end_of_input() {
+    let (r, txq, rxq) = new_initialized_for_test(EchoProcess::default()).await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    // Send echo requests and close the input before they are all handled ...
+    for msg_id in 0..5 {
+        let echo = Msg::<Echo, ()> {
+            src: "test".to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::Echo {
+                msg_id,
+                echo: Value::String(format!("boo! {}", msg_id)),
+            }),
+        };
+        txq.send(serde_json::to_string(&echo).expect("serialized"))
+            .await
+            .expect("sent echo request");
+    }
+    txq.close();
+
+    // ... the runtime stops cleanly ...
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+
+    // ... after sending all responses
+    for msg_id in 0..5 {
+        let echoed: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+        assert!(
+            matches!(echoed.body, Workload(Echo::EchoOk { in_reply_to, .. }) if in_reply_to == msg_id),
+            "{:?}",
+            echoed
+        );
+    }
+    assert!(rxq.recv().await.is_err(), "expected output to be closed");
+}
+
+#[test]
+async fn test_runtime_rejects_unparsable_messages() {
+    let (r, txq, rxq) = new_initialized_for_test(EchoProcess::default()).await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    for line in [
+        "not a message",
+        r#"{"src":"test","dest":"a","body":{"type":"frob","msg_id":3}}"#,
+        r#"{"src":"test","dest":"a","body":{"msg_id":4}}"#,
+        r#"{"src":"test","dest":"a","body":{"type":"echo","echo":"no msg_id"}}"#,
+        r#"{"src":"test","dest":"a","body":{"type":"echo","msg_id":5,"echo":"boo!"}}"#,
+    ] {
+        txq.send(line.to_string()).await.expect("sent line");
+    }
+
+    // Unparsable messages with a msg_id are rejected with an error reply ...
+    for (in_reply_to, code) in [
+        (3, ErrorCode::NotSupported),
+        (4, ErrorCode::MalformedRequest),
+    ] {
+        let reply: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+        match reply {
+            Msg {
+                src,
+                dest,
+                body: Body::Error(e),
+                ..
+            } => {
+                assert_eq!(src, "a");
+                assert_eq!(dest, "test");
+                assert_eq!(e.in_reply_to, in_reply_to);
+                assert_eq!(e.code, code);
+            }
+            msg => panic!("expected error, got {:?}", msg),
+        }
+    }
+
+    // ... and ingress continues delivering messages to the process
+    let echoed: Msg<Echo, ()> =
+        serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+    assert!(
+        matches!(echoed.body, Workload(Echo::EchoOk { in_reply_to: 5, .. })),
+        "{:?}",
+        echoed
+    );
+
+    txq.close();
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+#[test]
+async fn test_runtime_does_not_reply_to_malformed_replies() {
+    let (r, txq, rxq) = new_initialized_for_test(EchoProcess::default()).await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    for line in [
+        r#"{"src":"lin-kv","dest":"a","body":{"type":"read_ok","msg_id":5,"in_reply_to":1}}"#,
+        r#"{"src":"test","dest":"a","body":{"type":"echo","msg_id":6}}"#,
+    ] {
+        txq.send(line.to_string()).await.expect("sent line");
+    }
+
+    // Only the malformed request is answered
+    let reply: Msg<Echo, ()> =
+        serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+    assert!(
+        matches!(&reply.body, Body::Error(e) if e.in_reply_to == 6),
+        "{:?}",
+        reply
+    );
+
+    txq.close();
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+    assert!(rxq.try_recv().is_err());
+}
+
+#[test]
+async fn test_runtime_rejects_malformed_known_types() {
+    let (r, txq, rxq) = new_initialized_for_test(EchoProcess::default()).await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    for line in [
+        r#"{"src":"test","dest":"a","body":{"type":"echo","msg_id":5}}"#,
+        r#"{"src":"test","dest":"a","body":{"type":"echo_ok","msg_id":6,"echo":1}}"#,
+        r#"{"src":"test","dest":"a","body":{"type":"init","msg_id":7}}"#,
+        r#"{"src":"test","dest":"a","body":{"type":"error","msg_id":8}}"#,
+        r#"{"src":"test","dest":"a","body":{"type":"echoes","msg_id":9,"echo":"boo!"}}"#,
+    ] {
+        txq.send(line.to_string()).await.expect("sent line");
+    }
+
+    // Known types with missing or ill-typed fields are malformed, unknown types not supported
+    for (in_reply_to, code) in [
+        (5, ErrorCode::MalformedRequest),
+        (6, ErrorCode::MalformedRequest),
+        (7, ErrorCode::MalformedRequest),
+        (8, ErrorCode::MalformedRequest),
+        (9, ErrorCode::NotSupported),
+    ] {
+        let reply: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+        match reply.body {
+            Body::Error(e) => {
+                assert_eq!(e.in_reply_to, in_reply_to);
+                assert_eq!(e.code, code, "{}", e.text);
+            }
+            body => panic!("expected error, got {:?}", body),
+        }
+    }
+
+    txq.close();
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+/// Receives, and drops, all messages
+#[cfg(test)]
+struct DrainingProcess<W: DeserializeOwned + Serialize> {
+    net: ProcNet<W, ()>,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl<W> Process<W, ()> for DrainingProcess<W>
+where
+    W: DeserializeOwned + Serialize + Send + Sync,
+{
+    async fn init(&mut self, _: Vec<String>, net: ProcNet<W, ()>, _: Id, _: Vec<Id>) -> Status {
+        self.net = net;
+        Ok(())
+    }
+
+    async fn run(&self) -> Status {
+        while self.net.rxq.recv().await.is_ok() {}
+        Ok(()) // Runtime is shutting down.
+    }
+}
+
+#[test]
+async fn test_runtime_rejects_malformed_extensible_types() {
+    let process = DrainingProcess::<msg::Extensible<Echo>> {
+        net: Default::default(),
+    };
+    let (r, txq, rxq) = new_initialized_for_test(process).await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    for line in [
+        r#"{"src":"test","dest":"a","body":{"type":"echo","msg_id":5}}"#,
+        r#"{"src":"test","dest":"a","body":{"type":"echoes","msg_id":6,"echo":"boo!"}}"#,
+    ] {
+        txq.send(line.to_string()).await.expect("sent line");
+    }
+
+    for (in_reply_to, code) in [
+        (5, ErrorCode::MalformedRequest),
+        (6, ErrorCode::NotSupported),
+    ] {
+        let reply: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+        match reply.body {
+            Body::Error(e) => {
+                assert_eq!(e.in_reply_to, in_reply_to);
+                assert_eq!(e.code, code, "{}", e.text);
+            }
+            body => panic!("expected error, got {:?}", body),
+        }
+    }
+
+    txq.close();
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+#[test]
+async fn test_runtime_fatal_parse_failure() {
+    let (r, txq, rxq) = new_configured_for_test(EchoProcess::default(), |builder| {
+        builder.parse_failure_policy(ParseFailurePolicy::FatalAfter(1))
+    })
+    .await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    txq.send("not a message".to_string()).await.expect("sent");
+    txq.send(
+        r#"{"src":"test","dest":"a","body":{"type":"echo","msg_id":1,"echo":"boo!"}}"#.to_string(),
+    )
+    .await
+    .expect("sent");
+    let echoed: Msg<Echo, ()> =
+        serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+    assert!(matches!(
+        echoed.body,
+        Workload(Echo::EchoOk { in_reply_to: 1, .. })
+    ));
+
+    // The second failure is fatal
+    txq.send("not a message".to_string()).await.expect("sent");
+    let status = t.await.expect("joined");
+    assert!(
+        matches!(&status, Err(Error::Ingress(e)) if matches!(**e, Deserialize(_))),
+        "{:?}",
+        status
+    );
+}
+
+#[test]
+async fn test_runtime_stamps_msg_ids() {
+    let (r, txq, rxq) = new_configured_for_test(EchoProcess::default(), |builder| {
+        builder.stamp_msg_ids(true)
+    })
+    .await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    // The init_ok response used message ID 0, so stamping starts at 1
+    for msg_id in 1..4 {
+        let echo = Msg::<Echo, ()> {
+            src: "test".to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::Echo {
+                msg_id,
+                echo: Value::String(format!("boo! {}", msg_id)),
+            }),
+        };
+        txq.send(serde_json::to_string(&echo).expect("serialized"))
+            .await
+            .expect("sent echo request");
+        let echoed: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+        match echoed.body {
+            Workload(Echo::EchoOk {
+                in_reply_to,
+                msg_id: stamped,
+                ..
+            }) => {
+                assert_eq!(in_reply_to, msg_id);
+                assert_eq!(stamped, Some(msg_id));
+            }
+            body => panic!("expected echo_ok, got {:?}", body),
+        }
+    }
+
+    txq.close();
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+/// Sends three ticks to `test`, driven by a periodic timer
+#[cfg(test)]
+#[derive(Default)]
+struct TickProcess {
+    net: ProcNet<Echo, ()>,
+    id: Id,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Process<Echo, ()> for TickProcess {
+    async fn init(
+        &mut self,
+        _args: Vec<String>,
+        net: ProcNet<Echo, ()>,
+        id: Id,
+        _ids: Vec<Id>,
+    ) -> Status {
+        self.net = net;
+        self.id = id;
+        Ok(())
+    }
+
+    async fn run(&self) -> Status {
+        let timer = self.net.schedule_periodic(Duration::from_millis(5));
+        for tick in 0..3 {
+            match self.net.rxq.recv().await? {
+                Event::Timer(id) => assert_eq!(id, timer.id()),
+                event => panic!("unexpected event {:?}", event),
+            }
+            self.net
+                .txq
+                .send(Msg {
+                    src: self.id.clone(),
+                    dest: "test".to_string(),
+                    body: Workload(Echo::Echo {
+                        msg_id: self.net.next_msg_id(),
+                        echo: Value::String(format!("tick {}", tick)),
+                    }),
+                })
+                .await?;
+        }
+        timer.cancel();
+        Ok(())
+    }
+}
+
+#[test]
+async fn test_runtime_timers() {
+    let (r, _txq, rxq) = new_initialized_for_test(TickProcess::default()).await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    for tick in 0..3 {
+        let msg: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("tick")).expect("deserialized");
+        match msg.body {
+            Workload(Echo::Echo { echo, .. }) => {
+                assert_eq!(echo, Value::String(format!("tick {}", tick)))
+            }
+            body => panic!("expected tick, got {:?}", body),
+        }
+    }
+
+    // The process completed, so the runtime stops
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+}
+
+/// Echoes messages once its gate opens
+#[cfg(test)]
+struct GatedEchoProcess {
+    gate: Receiver<()>,
+    echo: EchoProcess,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl Process<Echo, ()> for GatedEchoProcess {
+    async fn init(
+        &mut self,
+        args: Vec<String>,
+        net: ProcNet<Echo, ()>,
+        id: Id,
+        ids: Vec<Id>,
+    ) -> Status {
+        self.echo.init(args, net, id, ids).await
+    }
+
+    async fn run(&self) -> Status {
+        let _ = self.gate.recv().await;
+        self.echo.run().await
+    }
+}
+
+/// Send echo requests 1 to 3 to a gated echo process with receive queue `depth`
+///
+/// Return the replies received before opening the gate, and after the end of input
+#[cfg(test)]
+async fn overflow_for_test(
+    depth: QueueDepth,
+    policy: OverflowPolicy,
+) -> (Vec<Msg<Echo, ()>>, Vec<Msg<Echo, ()>>) {
+    let (gate_txq, gate) = bounded(1);
+    let process = GatedEchoProcess {
+        gate,
+        echo: Default::default(),
+    };
+    let (r, txq, rxq) = new_configured_for_test(process, |builder| {
+        builder.ingress_depth(depth).overflow_policy(policy)
+    })
+    .await;
+    let r1 = r.clone();
+    let t = spawn(async move { r1.run().await });
+
+    for msg_id in 1..4 {
+        let echo = Msg::<Echo, ()> {
+            src: "test".to_string(),
+            dest: "a".to_string(),
+            body: Workload(Echo::Echo {
+                msg_id,
+                echo: Value::String(format!("boo! {}", msg_id)),
+            }),
+        };
+        txq.send(serde_json::to_string(&echo).expect("serialized"))
+            .await
+            .expect("sent echo request");
+    }
+    // Ingress has handled the echo requests once it rejects this malformed request
+    txq.send(r#"{"src":"test","dest":"a","body":{"msg_id":9}}"#.to_string())
+        .await
+        .expect("sent");
+    let mut before = vec![];
+    loop {
+        let msg: Msg<Echo, ()> =
+            serde_json::from_str(&rxq.recv().await.expect("response")).expect("deserialized");
+        match msg.body {
+            Body::Error(msg::Error { in_reply_to: 9, .. }) => break,
+            _ => before.push(msg),
+        }
+    }
+
+    gate_txq.send(()).await.expect("opened gate");
+    txq.close();
+    let status = t.await.expect("joined");
+    assert!(status.is_ok(), "{:?}", status);
+    let mut after = vec![];
+    while let Ok(line) = rxq.recv().await {
+        after.push(serde_json::from_str(&line).expect("deserialized"));
+    }
+    (before, after)
+}
+
+#[cfg(test)]
+fn in_reply_to_for_test(msgs: &[Msg<Echo, ()>]) -> Vec<(MsgId, Option<ErrorCode>)> {
+    msgs.iter()
+        .map(|msg| match &msg.body {
+            Workload(Echo::EchoOk { in_reply_to, .. }) => (*in_reply_to, None),
+            Body::Error(e) => (e.in_reply_to, Some(e.code)),
+            body => panic!("unexpected body {:?}", body),
+        })
+        .collect()
+}
+
+#[test]
+async fn test_runtime_overflow_drop_oldest() {
+    let (before, after) =
+        overflow_for_test(QueueDepth::Bounded(1), OverflowPolicy::DropOldest).await;
+    assert_eq!(in_reply_to_for_test(&before), vec![]);
+    assert_eq!(in_reply_to_for_test(&after), vec![(3, None)]);
+}
+
+#[test]
+async fn test_runtime_overflow_reject() {
+    let (before, after) = overflow_for_test(QueueDepth::Bounded(1), OverflowPolicy::Reject).await;
+    let unavailable = Some(ErrorCode::TemporarilyUnavailable);
+    assert_eq!(
+        in_reply_to_for_test(&before),
+        vec![(2, unavailable), (3, unavailable)]
+    );
+    assert_eq!(in_reply_to_for_test(&after), vec![(1, None)]);
+}
+
+#[test]
+async fn test_runtime_unbounded_ingress_queue() {
+    let (before, after) = overflow_for_test(QueueDepth::Unbounded, OverflowPolicy::Reject).await;
+    assert_eq!(in_reply_to_for_test(&before), vec![]);
+    assert_eq!(
+        in_reply_to_for_test(&after),
+        vec![(1, None), (2, None), (3, None)]
+    );
+}
@@ -0,0 +1,312 @@
+//! Timers for periodic and delayed process events
+//!
+//! A process schedules timers via its [ProcNet](crate::process::ProcNet). The
+//! [Runtime](crate::runtime::Runtime) fires them by delivering an [Event::Timer] to the process'
+//! receive queue, alongside received messages.
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::future::{poll_fn, Future};
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use async_channel::{bounded, Receiver, Sender};
+use futures::future::select;
+#[cfg(test)]
+use tokio::test;
+
+use crate::backend::sleep;
+use crate::process::Event;
+
+/// Timer identifier, unique per runtime
+pub type TimerId = u64;
+
+/// Handle to a scheduled timer
+///
+/// Dropping the handle does not cancel the timer.
+#[derive(Clone)]
+pub struct Timer {
+    id: TimerId,
+    timers: Arc<Timers>,
+}
+
+impl Timer {
+    /// Get the ID delivered in the timer's [Event::Timer]s
+    pub fn id(&self) -> TimerId {
+        self.id
+    }
+
+    /// Cancel the timer
+    ///
+    /// The timer will not fire after this call returns. An event already delivered to the process'
+    /// receive queue is not withdrawn.
+    pub fn cancel(&self) {
+        self.timers.cancel(self.id)
+    }
+}
+
+/// Scheduled timers
+///
+/// Timers are ordered by deadline, and fired by [Timers::run].
+pub(crate) struct Timers {
+    state: Mutex<TimerState>,
+    /// Wakes [Timers::run] when the earliest deadline may have changed, or on close
+    wake_txq: Sender<()>,
+    wake_rxq: Receiver<()>,
+}
+
+struct TimerState {
+    closed: bool,
+    next_id: TimerId,
+    /// Deadlines, including stale deadlines of cancelled and rescheduled timers
+    deadlines: BinaryHeap<Reverse<(Instant, TimerId)>>,
+    /// Live timers
+    timers: HashMap<TimerId, Schedule>,
+    /// Fired timers whose events are not yet delivered
+    firing: HashSet<TimerId>,
+    /// Wakes [Timers::run] when a timer event being delivered may have been cancelled, or on close
+    delivering: Option<Waker>,
+}
+
+struct Schedule {
+    deadline: Instant,
+    period: Option<Duration>,
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        let (wake_txq, wake_rxq) = bounded(1);
+        Self {
+            state: Mutex::new(TimerState {
+                closed: false,
+                next_id: 0,
+                deadlines: BinaryHeap::new(),
+                timers: HashMap::new(),
+                firing: HashSet::new(),
+                delivering: None,
+            }),
+            wake_txq,
+            wake_rxq,
+        }
+    }
+}
+
+impl Timers {
+    /// Schedule a timer to fire after `delay`, and then every `period` if any
+    pub(crate) fn schedule(timers: &Arc<Self>, delay: Duration, period: Option<Duration>) -> Timer {
+        let deadline = Self::now() + delay;
+        let id = {
+            let mut state = timers.state.lock().expect("timers lock");
+            let id = state.next_id;
+            state.next_id += 1;
+            state.deadlines.push(Reverse((deadline, id)));
+            state.timers.insert(id, Schedule { deadline, period });
+            id
+        };
+        timers.wake();
+        Timer {
+            id,
+            timers: timers.clone(),
+        }
+    }
+
+    fn cancel(&self, id: TimerId) {
+        let delivering = {
+            let mut state = self.state.lock().expect("timers lock");
+            state.timers.remove(&id);
+            state.firing.remove(&id);
+            state.delivering.take()
+        };
+        if let Some(waker) = delivering {
+            waker.wake();
+        }
+    }
+
+    /// Stop firing timers
+    pub(crate) fn close(&self) {
+        let delivering = {
+            let mut state = self.state.lock().expect("timers lock");
+            state.closed = true;
+            state.delivering.take()
+        };
+        if let Some(waker) = delivering {
+            waker.wake();
+        }
+        self.wake();
+    }
+
+    fn wake(&self) {
+        // A pending wake up is as good as a new one
+        let _ = self.wake_txq.try_send(());
+    }
+
+    fn now() -> Instant {
+        Instant::now()
+    }
+
+    /// Fire timers by sending their events to `txq`, until closed or `txq` is closed
+    pub(crate) async fn run<W, A>(&self, txq: &Sender<Event<W, A>>) {
+        loop {
+            let (fired, next_deadline) = match self.expire(Self::now()) {
+                Some(expired) => expired,
+                None => return,
+            };
+            for id in fired {
+                if !self.deliver(id, txq).await {
+                    return;
+                }
+            }
+            let woken = self.wake_rxq.recv();
+            match next_deadline {
+                Some(deadline) => {
+                    let timeout = sleep(deadline.saturating_duration_since(Self::now()));
+                    select(pin!(timeout), pin!(woken)).await;
+                }
+                None => {
+                    let _ = woken.await;
+                }
+            }
+        }
+    }
+
+    /// Deliver the event of the fired timer `id` to `txq`, unless the timer is cancelled first
+    ///
+    /// The timer is checked under the lock each time the send is polled, so the event is not
+    /// sent once [Timers::cancel] returns, even while waiting for room in `txq`.
+    ///
+    /// Return false IFF closed, or `txq` is closed
+    async fn deliver<W, A>(&self, id: TimerId, txq: &Sender<Event<W, A>>) -> bool {
+        let mut send = pin!(txq.send(Event::Timer(id)));
+        poll_fn(|cx| {
+            let mut state = self.state.lock().expect("timers lock");
+            if state.closed {
+                return Poll::Ready(false);
+            }
+            if !state.firing.contains(&id) {
+                return Poll::Ready(true);
+            }
+            match send.as_mut().poll(cx) {
+                Poll::Ready(Ok(())) => {
+                    state.firing.remove(&id);
+                    Poll::Ready(true)
+                }
+                Poll::Ready(Err(_)) => Poll::Ready(false),
+                Poll::Pending => {
+                    state.delivering = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Expire the timers due at `now`, rescheduling periodic timers
+    ///
+    /// Return the expired timers and the next deadline, or [None] IFF closed
+    fn expire(&self, now: Instant) -> Option<(Vec<TimerId>, Option<Instant>)> {
+        let mut state = self.state.lock().expect("timers lock");
+        if state.closed {
+            return None;
+        }
+        let TimerState {
+            deadlines,
+            timers,
+            firing,
+            ..
+        } = &mut *state;
+        let mut fired = vec![];
+        while let Some(Reverse((deadline, id))) = deadlines.peek().copied() {
+            match timers.get_mut(&id) {
+                Some(schedule) if schedule.deadline == deadline => {
+                    if deadline > now {
+                        return Some((fired, Some(deadline)));
+                    }
+                    fired.push(id);
+                    firing.insert(id);
+                    deadlines.pop();
+                    match schedule.period {
+                        Some(period) => {
+                            // Skip missed periods, rather than firing in a burst
+                            let mut next = deadline + period;
+                            if next <= now {
+                                next = now + period;
+                            }
+                            schedule.deadline = next;
+                            deadlines.push(Reverse((next, id)));
+                        }
+                        None => {
+                            timers.remove(&id);
+                        }
+                    }
+                }
+                _ => {
+                    // Stale deadline of a cancelled timer
+                    deadlines.pop();
+                }
+            }
+        }
+        Some((fired, None))
+    }
+}
+
+#[test]
+async fn test_timers() {
+    let timers: Arc<Timers> = Default::default();
+    let (txq, rxq) = bounded::<Event<(), ()>>(10);
+    let once = Timers::schedule(&timers, Duration::from_millis(30), None);
+    let periodic = Timers::schedule(
+        &timers,
+        Duration::from_millis(10),
+        Some(Duration::from_millis(10)),
+    );
+    let cancelled = Timers::schedule(&timers, Duration::from_millis(20), None);
+    cancelled.cancel();
+
+    let run = timers.run(&txq);
+    let check = async {
+        let mut periodic_fired = 0;
+        let mut once_fired = 0;
+        while periodic_fired < 5 {
+            match rxq.recv().await.expect("timer event") {
+                Event::Timer(id) if id == periodic.id() => periodic_fired += 1,
+                Event::Timer(id) if id == once.id() => once_fired += 1,
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        assert_eq!(once_fired, 1);
+        periodic.cancel();
+        timers.close();
+    };
+    futures::join!(run, check);
+}
+
+#[test]
+async fn test_timers_stop_when_queue_closed() {
+    let timers: Arc<Timers> = Default::default();
+    let (txq, rxq) = bounded::<Event<(), ()>>(10);
+    Timers::schedule(&timers, Duration::from_millis(1), None);
+    rxq.close();
+    // Returns rather than waiting to fire the timer
+    timers.run(&txq).await;
+}
+
+#[test]
+async fn test_timers_cancel_while_queue_full() {
+    let timers: Arc<Timers> = Default::default();
+    let (txq, rxq) = bounded::<Event<(), ()>>(1);
+    txq.send(Event::Timer(TimerId::MAX)).await.expect("queued");
+    let timer = Timers::schedule(&timers, Duration::from_millis(1), None);
+
+    let run = timers.run(&txq);
+    let check = async {
+        // The timer fires while the queue is full, then is cancelled before there is room
+        sleep(Duration::from_millis(50)).await;
+        timer.cancel();
+        assert!(matches!(rxq.recv().await, Ok(Event::Timer(TimerId::MAX))));
+        sleep(Duration::from_millis(50)).await;
+        timers.close();
+    };
+    futures::join!(run, check);
+    assert!(rxq.try_recv().is_err(), "cancelled timer fired");
+}
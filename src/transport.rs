@@ -0,0 +1,363 @@
+//! Transports carrying lines of JSON messages between a [Runtime](crate::runtime::Runtime) and
+//! the Maelstrom network
+//!
+//! Maelstrom runs a node as an OS process exchanging messages over `stdin` and `stdout`, see
+//! [StdioTransport]. The other transports connect a node to anything else speaking the same
+//! line protocol, e.g. a test within the local OS process ([ChannelTransport]), or a local router
+//! ([UnixTransport], [TcpTransport]).
+//!
+//! Streams are those of the executor backend selected by the `async-std` and `tokio` cargo
+//! features.
+use std::io;
+use std::net::{Ipv4Addr, Shutdown};
+#[cfg(unix)]
+use std::path::Path;
+
+use async_channel::{Receiver, Sender};
+#[cfg(not(feature = "tokio"))]
+use async_std::io::{
+    prelude::BufReadExt as AsyncBufReadExt, stdin, stdout, BufReader, Read as AsyncRead,
+    Write as AsyncWrite, WriteExt as AsyncWriteExt,
+};
+#[cfg(not(feature = "tokio"))]
+use async_std::net::TcpStream;
+#[cfg(all(unix, not(feature = "tokio")))]
+use async_std::os::unix::net::UnixStream;
+use async_trait::async_trait;
+use futures::lock::Mutex;
+#[cfg(feature = "tokio")]
+use socket2::{SockRef, Socket};
+#[cfg(feature = "tokio")]
+use tokio::io::{
+    stdin, stdout, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Stdin,
+};
+#[cfg(feature = "tokio")]
+use tokio::net::{tcp, TcpStream};
+#[cfg(all(unix, feature = "tokio"))]
+use tokio::net::{unix, UnixStream};
+#[cfg(test)]
+use tokio::test;
+
+use crate::{Error, Result, Status};
+
+/// Line transport
+///
+/// A transport sends and receives messages, one JSON message per line.
+#[async_trait]
+pub trait Transport {
+    /// Read the next line, without its line terminator
+    ///
+    /// Return [None] at the end of input
+    async fn read_line(&self) -> Result<Option<String>>;
+    /// Write a line, adding the line terminator
+    async fn write_line(&self, line: &str) -> Status;
+    /// Close the transport, so pending and future reads and writes fail or end
+    fn close(&self);
+}
+
+/// Transport over `stdin` and `stdout`
+///
+/// This is the transport Maelstrom expects.
+#[cfg_attr(not(feature = "tokio"), derive(Default))]
+pub struct StdioTransport {
+    #[cfg(feature = "tokio")]
+    stdin: Mutex<BufReader<Stdin>>,
+}
+
+#[cfg(feature = "tokio")]
+impl Default for StdioTransport {
+    fn default() -> Self {
+        Self {
+            stdin: Mutex::new(BufReader::new(stdin())),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for StdioTransport {
+    async fn read_line(&self) -> Result<Option<String>> {
+        let mut line = String::new();
+        #[cfg(feature = "tokio")]
+        let n = self.stdin.lock().await.read_line(&mut line).await?;
+        #[cfg(not(feature = "tokio"))]
+        let n = stdin().read_line(&mut line).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    async fn write_line(&self, line: &str) -> Status {
+        let mut stdout = stdout();
+        stdout.write_all(format!("{}\n", line).as_bytes()).await?;
+        stdout.flush().await?;
+        Ok(())
+    }
+
+    fn close(&self) {
+        // No op. stdin and stdout will close when Maelstrom closes its end.
+    }
+}
+
+/// Transport over in-memory queues, e.g. for running nodes within the local OS process
+///
+/// Each queue item is a line. Closing the receive queue ends the input.
+pub struct ChannelTransport {
+    rxq: Receiver<String>,
+    txq: Sender<String>,
+}
+
+impl ChannelTransport {
+    /// Create a transport reading lines from `rxq`, and writing lines to `txq`
+    pub fn new(rxq: Receiver<String>, txq: Sender<String>) -> Self {
+        Self { rxq, txq }
+    }
+}
+
+#[async_trait]
+impl Transport for ChannelTransport {
+    async fn read_line(&self) -> Result<Option<String>> {
+        // A closed queue is the end of input
+        Ok(self.rxq.recv().await.ok())
+    }
+
+    async fn write_line(&self, line: &str) -> Status {
+        self.txq
+            .send(line.to_string())
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe).into())
+    }
+
+    fn close(&self) {
+        self.rxq.close();
+        self.txq.close();
+    }
+}
+
+/// Transport over a Unix domain socket
+#[cfg(unix)]
+pub struct UnixTransport {
+    socket: UnixSocket,
+    lines: UnixLines,
+}
+
+#[cfg(all(unix, not(feature = "tokio")))]
+type UnixSocket = UnixStream;
+#[cfg(all(unix, not(feature = "tokio")))]
+type UnixLines = StreamLines<UnixStream, UnixStream>;
+#[cfg(all(unix, feature = "tokio"))]
+type UnixSocket = Socket;
+#[cfg(all(unix, feature = "tokio"))]
+type UnixLines = StreamLines<unix::OwnedReadHalf, unix::OwnedWriteHalf>;
+
+#[cfg(unix)]
+impl UnixTransport {
+    /// Connect to the socket at `path`
+    pub async fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        UnixStream::connect(path.as_ref()).await?.try_into()
+    }
+}
+
+#[cfg(unix)]
+impl TryFrom<UnixStream> for UnixTransport {
+    type Error = Error;
+
+    fn try_from(stream: UnixStream) -> Result<Self> {
+        #[cfg(not(feature = "tokio"))]
+        return Ok(Self {
+            lines: StreamLines::new(stream.clone(), stream.clone()),
+            socket: stream,
+        });
+        #[cfg(feature = "tokio")]
+        {
+            let socket = SockRef::from(&stream).try_clone()?;
+            let (reader, writer) = stream.into_split();
+            Ok(Self {
+                socket,
+                lines: StreamLines::new(reader, writer),
+            })
+        }
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Transport for UnixTransport {
+    async fn read_line(&self) -> Result<Option<String>> {
+        self.lines.read_line().await
+    }
+
+    async fn write_line(&self, line: &str) -> Status {
+        self.lines.write_line(line).await
+    }
+
+    fn close(&self) {
+        // The peer may have already closed the socket
+        let _ = self.socket.shutdown(Shutdown::Both);
+    }
+}
+
+/// Transport over a TCP connection on localhost
+pub struct TcpTransport {
+    socket: TcpSocket,
+    lines: TcpLines,
+}
+
+#[cfg(not(feature = "tokio"))]
+type TcpSocket = TcpStream;
+#[cfg(not(feature = "tokio"))]
+type TcpLines = StreamLines<TcpStream, TcpStream>;
+#[cfg(feature = "tokio")]
+type TcpSocket = Socket;
+#[cfg(feature = "tokio")]
+type TcpLines = StreamLines<tcp::OwnedReadHalf, tcp::OwnedWriteHalf>;
+
+impl TcpTransport {
+    /// Connect to `port` on localhost
+    pub async fn connect(port: u16) -> Result<Self> {
+        TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await?
+            .try_into()
+    }
+}
+
+impl TryFrom<TcpStream> for TcpTransport {
+    type Error = Error;
+
+    fn try_from(stream: TcpStream) -> Result<Self> {
+        #[cfg(not(feature = "tokio"))]
+        return Ok(Self {
+            lines: StreamLines::new(stream.clone(), stream.clone()),
+            socket: stream,
+        });
+        #[cfg(feature = "tokio")]
+        {
+            let socket = SockRef::from(&stream).try_clone()?;
+            let (reader, writer) = stream.into_split();
+            Ok(Self {
+                socket,
+                lines: StreamLines::new(reader, writer),
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn read_line(&self) -> Result<Option<String>> {
+        self.lines.read_line().await
+    }
+
+    async fn write_line(&self, line: &str) -> Status {
+        self.lines.write_line(line).await
+    }
+
+    fn close(&self) {
+        // The peer may have already closed the connection
+        let _ = self.socket.shutdown(Shutdown::Both);
+    }
+}
+
+/// Lines read from and written to a byte stream
+///
+/// Reads and writes are independent, so a pending read does not block writes. The stream traits
+/// are those of the selected backend, imported under the tokio names.
+struct StreamLines<R, W> {
+    reader: Mutex<BufReader<R>>,
+    writer: Mutex<W>,
+}
+
+impl<R, W> StreamLines<R, W>
+where
+    R: AsyncRead + Unpin + Send,
+    W: AsyncWrite + Unpin + Send,
+{
+    fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader: Mutex::new(BufReader::new(reader)),
+            writer: Mutex::new(writer),
+        }
+    }
+
+    async fn read_line(&self) -> Result<Option<String>> {
+        let mut line = String::new();
+        if self.reader.lock().await.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    async fn write_line(&self, line: &str) -> Status {
+        let mut writer = self.writer.lock().await;
+        writer.write_all(format!("{}\n", line).as_bytes()).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Exchange lines between connected transports `a` and `b`, then close `a`
+#[cfg(test)]
+async fn exchange_lines_for_test(a: &dyn Transport, b: &dyn Transport) {
+    a.write_line(r#"{"line":1}"#).await.expect("written");
+    a.write_line(r#"{"line":2}"#).await.expect("written");
+    b.write_line(r#"{"line":3}"#).await.expect("written");
+    assert_eq!(
+        b.read_line().await.expect("read").as_deref(),
+        Some(r#"{"line":1}"#)
+    );
+    assert_eq!(
+        b.read_line().await.expect("read").as_deref(),
+        Some(r#"{"line":2}"#)
+    );
+    assert_eq!(
+        a.read_line().await.expect("read").as_deref(),
+        Some(r#"{"line":3}"#)
+    );
+    a.close();
+    assert_eq!(b.read_line().await.expect("read"), None);
+}
+
+#[test]
+async fn test_channel_transport() {
+    let (atxq, brxq) = async_channel::bounded(10);
+    let (btxq, arxq) = async_channel::bounded(10);
+    let a = ChannelTransport::new(arxq, atxq);
+    let b = ChannelTransport::new(brxq, btxq);
+    exchange_lines_for_test(&a, &b).await;
+    assert!(a.write_line("closed").await.is_err());
+}
+
+#[test]
+async fn test_tcp_transport() {
+    #[cfg(not(feature = "tokio"))]
+    use async_std::net::TcpListener;
+    #[cfg(feature = "tokio")]
+    use tokio::net::TcpListener;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .await
+        .expect("bound");
+    let port = listener.local_addr().expect("address").port();
+    let (a, accepted) = futures::join!(TcpTransport::connect(port), listener.accept());
+    let a = a.expect("connected");
+    let b = TcpTransport::try_from(accepted.expect("accepted").0).expect("transport");
+    exchange_lines_for_test(&a, &b).await;
+}
+
+#[cfg(unix)]
+#[test]
+async fn test_unix_transport() {
+    let path = std::env::temp_dir().join(format!("async-maelstrom-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    #[cfg(not(feature = "tokio"))]
+    let listener = async_std::os::unix::net::UnixListener::bind(&path)
+        .await
+        .expect("bound");
+    #[cfg(feature = "tokio")]
+    let listener = tokio::net::UnixListener::bind(&path).expect("bound");
+    let (a, accepted) = futures::join!(UnixTransport::connect(&path), listener.accept());
+    let a = a.expect("connected");
+    let b = UnixTransport::try_from(accepted.expect("accepted").0).expect("transport");
+    exchange_lines_for_test(&a, &b).await;
+    let _ = std::fs::remove_file(&path);
+}